@@ -0,0 +1,152 @@
+use crate::entrypoint::{ApiError, AppError};
+use anyhow::anyhow;
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use axum::extract::{FromRequestParts, OptionalFromRequestParts, Path, Request};
+use axum::http::{header, request::Parts};
+use axum::middleware::Next;
+use axum::response::Response;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Lifetime of an issued token
+const TOKEN_VALIDITY_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// Id of the user
+    sub: i32,
+    /// Expiration, as a unix timestamp
+    exp: i64,
+}
+
+fn jwt_secret() -> Result<String, anyhow::Error> {
+    Ok(std::env::var("JWT_SECRET")?)
+}
+
+/// Checked in place of a stored hash when the email is unknown, so that a
+/// failed login takes as long whether or not the account exists
+static DUMMY_HASH: LazyLock<Option<String>> =
+    LazyLock::new(|| hash_password_blocking("not a password").ok());
+
+fn hash_password_blocking(password: &str) -> Result<String, anyhow::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {e}"))?;
+    Ok(hash.to_string())
+}
+
+fn verify_password_blocking(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Runs on the blocking pool, argon2 being slow on purpose
+pub async fn hash_password(password: String) -> Result<String, anyhow::Error> {
+    tokio::task::spawn_blocking(move || hash_password_blocking(&password)).await?
+}
+
+/// False as well when `password_hash` is not a valid argon2 hash. Without a
+/// `password_hash` the password is checked against a dummy hash and refused.
+pub async fn verify_password(password: String, password_hash: Option<String>) -> bool {
+    tokio::task::spawn_blocking(move || match password_hash {
+        Some(password_hash) => verify_password_blocking(&password, &password_hash),
+        None => {
+            if let Some(dummy_hash) = DUMMY_HASH.as_deref() {
+                verify_password_blocking(&password, dummy_hash);
+            }
+            false
+        }
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Signed token identifying `user_id`, sent back as `Authorization: Bearer`
+pub fn create_token(user_id: i32) -> Result<String, anyhow::Error> {
+    let claims = Claims {
+        sub: user_id,
+        exp: (chrono::Utc::now() + chrono::Duration::days(TOKEN_VALIDITY_DAYS)).timestamp(),
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret()?.as_bytes()),
+    )?)
+}
+
+fn decode_token(token: &str) -> Result<i32, AppError> {
+    let claims = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret()?.as_bytes()),
+        &Validation::default(),
+    )
+    .map_err(|_| AppError::from(ApiError::Unauthorized))?;
+    Ok(claims.claims.sub)
+}
+
+/// The logged-in user, resolved from the `Authorization: Bearer` header.
+/// Requests without a valid token are refused with 401.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthUser {
+    pub id: i32,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        <AuthUser as OptionalFromRequestParts<S>>::from_request_parts(parts, state)
+            .await?
+            .ok_or(ApiError::Unauthorized.into())
+    }
+}
+
+/// `Option<AuthUser>` is None for anonymous requests, an invalid token is
+/// still refused.
+impl<S: Send + Sync> OptionalFromRequestParts<S> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        let Some(authorization) = parts.headers.get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::BadRequest)?;
+        Ok(Some(AuthUser {
+            id: decode_token(token)?,
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserPath {
+    user_id: i32,
+}
+
+/// Layer of the `/users/{user_id}` routes: only the logged-in user
+/// `user_id` gets through, anonymous callers get 401 and others 403.
+pub async fn require_same_user(
+    Path(path): Path<UserPath>,
+    user: AuthUser,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if user.id != path.user_id {
+        return Err(ApiError::Forbidden.into());
+    }
+    Ok(next.run(request).await)
+}
@@ -1,12 +1,117 @@
+pub mod balances;
+pub mod changes;
+pub mod currencies;
+pub mod events;
 pub mod group_members;
 pub mod groups;
+pub mod invites;
+pub mod roles;
+pub mod settlements;
+pub mod share_links;
+pub mod splits;
 pub mod status;
+pub mod sync;
+pub mod token_aliases;
+pub mod tombstones;
 pub mod transactions;
+pub mod users;
+pub mod validation;
 pub use crate::state_server;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde::Serialize;
+use diesel::result::DatabaseErrorKind;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::fmt;
+use validation::ValidationErrors;
+
+/// Errors reported to the clients. `code` is stable, clients can rely on it
+/// to pick the message they show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiError {
+    /// The request cannot be read
+    BadRequest,
+    /// Missing, invalid or expired login token
+    Unauthorized,
+    /// Wrong email or password
+    InvalidCredentials,
+    /// The role of the caller in the group does not allow it
+    Forbidden,
+    /// A share link was used on a route that writes
+    ReadOnlyToken,
+    NotFound,
+    /// The row was deleted, or the token no longer gives access
+    Gone,
+    /// The change contradicts a row of the server
+    Conflict,
+    EmailTaken,
+    NicknameTaken,
+    /// The member is claimed by another user, or the user already claimed
+    /// another member of the group
+    MemberAlreadyClaimed,
+    /// The change would leave an owned group without owner
+    LastOwner,
+    /// Transaction with wrong fields, the list of FieldError in `content`
+    InvalidTransaction,
+    InvalidEmail,
+    PasswordTooShort,
+    /// Any other invalid value, details in `content`
+    InvalidInput,
+    Internal,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized | ApiError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden | ApiError::ReadOnlyToken => StatusCode::FORBIDDEN,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::Gone => StatusCode::GONE,
+            ApiError::Conflict
+            | ApiError::EmailTaken
+            | ApiError::NicknameTaken
+            | ApiError::MemberAlreadyClaimed
+            | ApiError::LastOwner => StatusCode::CONFLICT,
+            ApiError::InvalidTransaction
+            | ApiError::InvalidEmail
+            | ApiError::PasswordTooShort
+            | ApiError::InvalidInput => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest => "bad_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::InvalidCredentials => "invalid_credentials",
+            ApiError::Forbidden => "forbidden",
+            ApiError::ReadOnlyToken => "read_only_token",
+            ApiError::NotFound => "not_found",
+            ApiError::Gone => "gone",
+            ApiError::Conflict => "conflict",
+            ApiError::EmailTaken => "email_taken",
+            ApiError::NicknameTaken => "nickname_taken",
+            ApiError::MemberAlreadyClaimed => "member_already_claimed",
+            ApiError::LastOwner => "last_owner",
+            ApiError::InvalidTransaction => "invalid_transaction",
+            ApiError::InvalidEmail => "invalid_email",
+            ApiError::PasswordTooShort => "password_too_short",
+            ApiError::InvalidInput => "invalid_input",
+            ApiError::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl std::error::Error for ApiError {}
 
 pub struct AppError<T: Serialize = ()> {
     error: anyhow::Error,
@@ -14,28 +119,55 @@ pub struct AppError<T: Serialize = ()> {
 }
 
 impl<T: Serialize> AppError<T> {
+    /// `error` with details for the client
+    pub fn with_content(error: ApiError, content: T) -> Self {
+        Self {
+            error: error.into(),
+            content: Some(content),
+        }
+    }
+
     fn convert_content(&self) -> serde_json::value::Value {
         match &self.content {
             Some(content) => serde_json::to_value(content).unwrap_or_default(),
-            None => serde_json::to_value(serde_json::value::Value::Null).unwrap_or_default(),
+            None => match self.error.downcast_ref::<ValidationErrors>() {
+                Some(errors) => serde_json::to_value(&errors.0).unwrap_or_default(),
+                None => serde_json::to_value(serde_json::value::Value::Null).unwrap_or_default(),
+            },
+        }
+    }
+
+    /// The error the client gets: ApiError raised by the handlers, diesel
+    /// errors with a meaning for the client, Internal for the rest
+    pub fn api_error(&self) -> ApiError {
+        if let Some(error) = self.error.downcast_ref::<ApiError>() {
+            return *error;
+        }
+        if self.error.is::<ValidationErrors>() {
+            return ApiError::InvalidTransaction;
+        }
+        match self.error.downcast_ref::<diesel::result::Error>() {
+            Some(diesel::NotFound) => ApiError::NotFound,
+            Some(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                ApiError::Conflict
+            }
+            Some(diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::ForeignKeyViolation | DatabaseErrorKind::CheckViolation,
+                _,
+            )) => ApiError::InvalidInput,
+            _ => ApiError::Internal,
         }
     }
 
-    fn message(&self) -> String {
+    fn message(&self, error: ApiError) -> String {
         let content = self.convert_content();
-        let m = json!({"message":self.error.to_string(), "content":content});
+        let m = json!({"code": error.code(), "message":self.error.to_string(), "content":content});
         serde_json::to_string(&m).unwrap_or(String::from(""))
     }
 
     pub fn convert_message(&self) -> (StatusCode, String) {
-        if let Some(diesel_error) = self.error.downcast_ref() {
-            return match diesel_error {
-                diesel::NotFound => (StatusCode::NOT_FOUND, self.message()),
-                _ => (StatusCode::INTERNAL_SERVER_ERROR, self.message()),
-            };
-        }
-
-        (StatusCode::INTERNAL_SERVER_ERROR, self.message())
+        let error = self.api_error();
+        (error.status(), self.message(error))
     }
 }
 
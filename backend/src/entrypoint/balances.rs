@@ -0,0 +1,203 @@
+use crate::entrypoint::group_members::GroupMemberNoDate;
+use crate::entrypoint::groups::{get_group, get_user_groups, GroupNoID};
+use crate::entrypoint::share_links::get_readable_group_id;
+use crate::entrypoint::transactions::TransactionKind;
+use crate::entrypoint::AppError;
+use crate::schema::group_members;
+use crate::schema::transaction_debts;
+use crate::schema::transactions;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MemberBalance {
+    pub member: GroupMemberNoDate,
+    pub paid: BigDecimal,
+    pub owed: BigDecimal,
+    pub balance: BigDecimal,
+    /// Share of the group spending, transfers between members excluded.
+    pub spent: BigDecimal,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct BalancesResponse {
+    pub currency_id: String,
+    pub balances: Vec<MemberBalance>,
+}
+
+/// Balance of the member claimed by a user in one group
+#[derive(Deserialize, Serialize, Debug)]
+pub struct GroupBalance {
+    pub group: GroupNoID,
+    pub balance: MemberBalance,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CurrencyBalance {
+    pub currency_id: String,
+    pub balance: BigDecimal,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UserBalancesResponse {
+    pub groups: Vec<GroupBalance>,
+    /// Sum of the balances of every group sharing the same currency
+    pub totals: Vec<CurrencyBalance>,
+}
+
+#[derive(Default)]
+struct Totals {
+    paid: BigDecimal,
+    owed: BigDecimal,
+    spent: BigDecimal,
+}
+
+/// Net balance of every member of a group, converted into the group currency.
+/// A positive balance means the group owes money to the member.
+/// Incomes are counted negatively: the payer received the money on behalf of
+/// the debtors.
+pub fn get_balances(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<MemberBalance>, anyhow::Error> {
+    let members = group_members::table
+        .select((
+            group_members::id,
+            group_members::uuid,
+            group_members::nickname,
+        ))
+        .filter(group_members::group_id.eq(group_id))
+        .load::<(i32, String, String)>(conn)?;
+
+    let paid = transactions::table
+        .select((
+            transactions::paid_by,
+            transactions::amount,
+            transactions::exchange_rate,
+            transactions::kind,
+        ))
+        .filter(transactions::group_id.eq(group_id))
+        .load::<(i32, BigDecimal, BigDecimal, TransactionKind)>(conn)?;
+
+    let owed = transaction_debts::table
+        .inner_join(transactions::table)
+        .select((
+            transaction_debts::group_member_id,
+            transaction_debts::amount,
+            transactions::exchange_rate,
+            transactions::kind,
+        ))
+        .filter(transactions::group_id.eq(group_id))
+        .load::<(i32, BigDecimal, BigDecimal, TransactionKind)>(conn)?;
+
+    let mut totals: HashMap<i32, Totals> = members
+        .iter()
+        .map(|(id, _, _)| (*id, Totals::default()))
+        .collect();
+
+    for (member_id, amount, exchange_rate, kind) in paid {
+        if let Some(total) = totals.get_mut(&member_id) {
+            match kind {
+                TransactionKind::Income => total.paid -= amount * exchange_rate,
+                _ => total.paid += amount * exchange_rate,
+            }
+        }
+    }
+    for (member_id, amount, exchange_rate, kind) in owed {
+        if let Some(total) = totals.get_mut(&member_id) {
+            let amount = amount * exchange_rate;
+            match kind {
+                TransactionKind::Expense => {
+                    total.spent += &amount;
+                    total.owed += amount;
+                }
+                TransactionKind::Income => {
+                    total.spent -= &amount;
+                    total.owed -= amount;
+                }
+                TransactionKind::Transfer => total.owed += amount,
+            }
+        }
+    }
+
+    let mut balances = members
+        .into_iter()
+        .map(|(id, uuid, nickname)| {
+            let Totals { paid, owed, spent } = totals.remove(&id).unwrap_or_default();
+            MemberBalance {
+                member: GroupMemberNoDate { uuid, nickname },
+                balance: &paid - &owed,
+                paid,
+                owed,
+                spent,
+            }
+        })
+        .collect::<Vec<MemberBalance>>();
+    balances.sort_by(|a, b| {
+        a.member
+            .nickname
+            .cmp(&b.member.nickname)
+            .then_with(|| a.member.uuid.cmp(&b.member.uuid))
+    });
+
+    Ok(balances)
+}
+
+///groups/{token_id}/balances
+pub async fn handler_get_balances(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+) -> Result<Json<BalancesResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let group = get_group(group_id, &mut conn)?;
+    let balances = get_balances(group_id, &mut conn)?;
+
+    Ok(Json(BalancesResponse {
+        currency_id: group.currency_id,
+        balances,
+    }))
+}
+
+///users/{user_id}/balances
+pub async fn handler_users_balances(
+    State(state_server): State<state_server::StateServer>,
+    Path(user_id): Path<i32>,
+) -> Result<Json<UserBalancesResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let mut groups = vec![];
+    let mut totals: HashMap<String, BigDecimal> = HashMap::new();
+    for (group_id, group, member_uuid) in get_user_groups(user_id, &mut conn)? {
+        let Some(balance) = get_balances(group_id, &mut conn)?
+            .into_iter()
+            .find(|balance| balance.member.uuid == member_uuid)
+        else {
+            continue;
+        };
+        *totals.entry(group.currency_id.clone()).or_default() += &balance.balance;
+        groups.push(GroupBalance { group, balance });
+    }
+    groups.sort_by(|a, b| a.group.name.cmp(&b.group.name));
+
+    let mut totals = totals
+        .into_iter()
+        .map(|(currency_id, balance)| CurrencyBalance {
+            currency_id,
+            balance,
+        })
+        .collect::<Vec<CurrencyBalance>>();
+    totals.sort_by(|a, b| a.currency_id.cmp(&b.currency_id));
+
+    Ok(Json(UserBalancesResponse { groups, totals }))
+}
@@ -0,0 +1,145 @@
+use crate::entrypoint::group_members::{get_group_members, GroupMember};
+use crate::entrypoint::groups::{get_group, GroupNoID};
+use crate::entrypoint::share_links::{find_share_link, get_readable_group_id};
+use crate::entrypoint::tombstones::{get_tombstone, get_tombstones, Tombstone, TombstoneEntity};
+use crate::entrypoint::transactions::{get_transactions, TransactionResponse};
+use crate::entrypoint::AppError;
+use crate::schema::{group_members, groups, tombstones, transactions};
+pub use crate::state_server;
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ChangesQuery {
+    pub since: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangesResponse {
+    /// The group, only if it was modified after the cursor
+    pub group: Option<GroupNoID>,
+    pub group_members: Vec<GroupMember>,
+    pub transactions: Vec<TransactionResponse>,
+    /// Rows deleted on the server, including the group itself
+    pub deleted: Vec<Tombstone>,
+    /// Cursor to send as `since` on the next synchronisation
+    pub cursor: Option<NaiveDateTime>,
+}
+
+/// Server time before which every write to the group is committed. The
+/// writers hold a shared lock on the group from the first row they stamp
+/// until they commit: taking it exclusively waits for them.
+fn committed_before(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<NaiveDateTime, anyhow::Error> {
+    conn.transaction::<NaiveDateTime, anyhow::Error, _>(|conn| {
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+            .bind::<diesel::sql_types::Integer, _>(group_id)
+            .execute(conn)?;
+        Ok(
+            diesel::select(diesel::dsl::sql::<diesel::sql_types::Timestamp>(
+                "clock_timestamp() AT TIME ZONE 'UTC'",
+            ))
+            .get_result::<NaiveDateTime>(conn)?,
+        )
+    })
+}
+
+/// Last server write to the rows of a group. The cursor is taken from
+/// `updated_at`, set by the database, rather than from the `modified_at` of
+/// the clients: an offline client pushes rows modified long before they reach
+/// the server. It never passes a write still to be committed, which would
+/// otherwise be missed by the next synchronisation.
+fn get_cursor(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<NaiveDateTime>, anyhow::Error> {
+    let committed = committed_before(group_id, conn)?;
+    let group = groups::table
+        .filter(groups::id.eq(group_id))
+        .select(diesel::dsl::max(groups::updated_at))
+        .first::<Option<NaiveDateTime>>(conn)?;
+    let members = group_members::table
+        .filter(group_members::group_id.eq(group_id))
+        .select(diesel::dsl::max(group_members::updated_at))
+        .first::<Option<NaiveDateTime>>(conn)?;
+    let transactions = transactions::table
+        .filter(transactions::group_id.eq(group_id))
+        .select(diesel::dsl::max(transactions::updated_at))
+        .first::<Option<NaiveDateTime>>(conn)?;
+    let deleted = tombstones::table
+        .filter(tombstones::group_id.eq(group_id))
+        .select(diesel::dsl::max(tombstones::updated_at))
+        .first::<Option<NaiveDateTime>>(conn)?;
+    Ok([group, members, transactions, deleted]
+        .into_iter()
+        .flatten()
+        .max()
+        .map(|cursor| cursor.min(committed)))
+}
+
+///groups/{token_id}/changes?since={cursor}
+pub async fn handler_get_changes(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ChangesResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let group_id = match get_readable_group_id(&token, &mut conn) {
+        Ok(group_id) => group_id,
+        Err(error) => {
+            let Some(tombstone) = get_tombstone(TombstoneEntity::Group, &token, &mut conn)? else {
+                return Err(error.into());
+            };
+            let cursor = tombstones::table
+                .filter(tombstones::entity.eq(TombstoneEntity::Group))
+                .filter(tombstones::uuid.eq(&token))
+                .select(tombstones::updated_at)
+                .first::<NaiveDateTime>(&mut conn)?;
+            return Ok(Json(ChangesResponse {
+                group: None,
+                group_members: vec![],
+                transactions: vec![],
+                cursor: Some(cursor),
+                deleted: vec![tombstone],
+            }));
+        }
+    };
+    // Taken before loading the rows: a row written in between is sent again
+    // on the next synchronisation rather than missed
+    let cursor = get_cursor(group_id, &mut conn)?.max(query.since);
+    let group_updated_at = groups::table
+        .filter(groups::id.eq(group_id))
+        .select(groups::updated_at)
+        .first::<NaiveDateTime>(&mut conn)?;
+    let group = match query.since {
+        Some(since) if group_updated_at <= since => None,
+        _ => {
+            let mut group = get_group(group_id, &mut conn)?;
+            // A share link does not leak the edit token
+            if find_share_link(&token, &mut conn)?.is_some() {
+                group.token = token.clone();
+            }
+            Some(group)
+        }
+    };
+    let group_members = get_group_members(group_id, query.since, &mut conn)?;
+    let transactions = get_transactions(group_id, query.since, &mut conn)?;
+    let deleted = get_tombstones(group_id, query.since, &mut conn)?;
+
+    Ok(Json(ChangesResponse {
+        group,
+        group_members,
+        transactions,
+        deleted,
+        cursor,
+    }))
+}
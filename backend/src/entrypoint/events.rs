@@ -0,0 +1,95 @@
+use crate::entrypoint::share_links::{find_share_link, get_readable_group_id};
+use crate::entrypoint::token_aliases::get_group_tokens;
+use crate::entrypoint::tombstones::TombstoneEntity;
+use crate::entrypoint::AppError;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupEventKind {
+    Updated,
+    Deleted,
+}
+
+impl GroupEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupEventKind::Updated => "updated",
+            GroupEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A row of a group changed on the server
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GroupEvent {
+    /// Token of the group
+    pub token: String,
+    pub kind: GroupEventKind,
+    pub entity: TombstoneEntity,
+    /// Uuid of the row, the token for the group
+    pub uuid: String,
+}
+
+/// False as well when the link cannot be checked, the client reconnects
+fn is_share_link_active(token: &str, pool: &state_server::DbPool) -> bool {
+    pool.get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| find_share_link(token, &mut conn))
+        .is_ok_and(|share_link| {
+            share_link.is_some_and(|(_, share_link)| share_link.revoked_at.is_none())
+        })
+}
+
+///groups/{token_id}/events
+/// Server-sent events, one per change of the group. A `resync` event is sent
+/// when notifications were dropped, the client should then fetch the changes.
+pub async fn handler_group_events(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    // Changes are notified under the token they were made with
+    let tokens = get_group_tokens(get_readable_group_id(&token, &mut conn)?, &mut conn)?;
+    // A share link sees the events under its own token, so that they do not
+    // leak the edit token
+    let read_only = find_share_link(&token, &mut conn)?.is_some();
+
+    let pool = state_server.pool.clone();
+    let link_token = token.clone();
+    let group_tokens = tokens.clone();
+    let stream = BroadcastStream::new(state_server.events.subscribe())
+        // A revoked share link stops receiving at the next event of its group
+        .take_while(move |event| match event {
+            Ok(event) if read_only && group_tokens.contains(&event.token) => {
+                is_share_link_active(&link_token, &pool)
+            }
+            _ => true,
+        })
+        .filter_map(move |event| match event {
+            Ok(mut event) if tokens.contains(&event.token) => {
+                if read_only {
+                    if event.entity == TombstoneEntity::Group {
+                        event.uuid = token.clone();
+                    }
+                    event.token = token.clone();
+                }
+                Some(Ok(Event::default()
+                    .event(event.kind.as_str())
+                    .json_data(&event)
+                    .unwrap_or_default()))
+            }
+            Ok(_) => None,
+            Err(_) => Some(Ok(Event::default().event("resync").data(""))),
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
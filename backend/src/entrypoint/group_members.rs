@@ -1,20 +1,31 @@
-use crate::entrypoint::AppError;
+use crate::auth::AuthUser;
+use crate::entrypoint::events::GroupEventKind;
+use crate::entrypoint::roles::{has_owner, require_role, Role};
+use crate::entrypoint::share_links::get_readable_group_id;
+use crate::entrypoint::sync::{ItemResult, SyncStatus};
+use crate::entrypoint::tombstones::{
+    add_redirect, add_tombstone, get_tombstone, is_deleted, TombstoneEntity,
+};
+use crate::entrypoint::{ApiError, AppError};
 use crate::schema::group_members;
 use crate::schema::groups;
+use crate::schema::member_renames;
 pub use crate::state_server;
 
-use anyhow::anyhow;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::Json,
 };
 
 use crate::entrypoint::groups::get_group_id;
+use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
-const MAX_MEMBER_NAME_SIZE : usize = 250;
+use std::collections::HashMap;
+use unicode_normalization::UnicodeNormalization;
+const MAX_MEMBER_NAME_SIZE: usize = 250;
 
 #[derive(Queryable, Selectable, Debug, Serialize, Insertable, Deserialize, AsChangeset, Clone)]
 #[diesel(table_name = crate::schema::group_members)]
@@ -23,6 +34,15 @@ pub struct GroupMember {
     pub uuid: String,
     pub nickname: String,
     pub modified_at: NaiveDateTime,
+    /// Former member, kept for the history and balances. Only changed by
+    /// archiving the member, not by saving it.
+    #[serde(default)]
+    pub archived: bool,
+    /// Default weight in equal and shares splits, such as 2 for a couple or
+    /// 0.5 for a child. None counts as 1, and keeps the stored weight when
+    /// saving the member: 1 resets it.
+    #[serde(default)]
+    pub weight: Option<BigDecimal>,
 }
 
 impl GroupMember {
@@ -31,11 +51,18 @@ impl GroupMember {
             uuid: uuid::Uuid::new_v4().to_string(),
             nickname: name.to_string(),
             modified_at: chrono::Utc::now().naive_utc(),
+            archived: false,
+            weight: None,
         }
     }
+
+    pub fn with_weight(mut self, weight: BigDecimal) -> Self {
+        self.weight = Some(weight);
+        self
+    }
 }
 
-#[derive(Deserialize, Serialize, Queryable, Debug, Clone)]
+#[derive(Deserialize, Serialize, Queryable, Debug, Clone, PartialEq)]
 pub struct GroupMemberNoDate {
     pub uuid: String,
     pub nickname: String,
@@ -60,73 +87,167 @@ impl From<&GroupMember> for GroupMemberNoDate {
 }
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::PooledConnection;
-pub fn get_all_members(
-    token: &str,
+/// Members of a group, only the ones written on the server after `since` if given
+pub fn get_group_members(
+    group_id: i32,
+    since: Option<NaiveDateTime>,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
 ) -> Result<Vec<GroupMember>, anyhow::Error> {
-    let results = groups::table
-        .inner_join(group_members::table.on(groups::id.eq(group_members::group_id)))
-        .filter(groups::token.eq(token))
+    let mut query = group_members::table
         .select(GroupMember::as_select())
-        .get_results::<GroupMember>(conn)?;
+        .filter(group_members::group_id.eq(group_id))
+        .order(group_members::id)
+        .into_boxed();
+    if let Some(since) = since {
+        query = query.filter(group_members::updated_at.gt(since));
+    }
+
+    Ok(query.load::<GroupMember>(conn)?)
+}
 
-    Ok(results)
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct GroupMembersQuery {
+    /// Leave out the archived members, as when picking the members of a new
+    /// transaction
+    #[serde(default)]
+    pub active: bool,
 }
 
 pub async fn handler_group_members(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    Query(query): Query<GroupMembersQuery>,
 ) -> Result<Json<Vec<GroupMember>>, AppError> {
     let mut conn = state_server.pool.get()?;
 
-    let results = get_all_members(&token, &mut conn)?;
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let mut results = get_group_members(group_id, None, &mut conn)?;
+    if query.active {
+        results.retain(|member| !member.archived);
+    }
 
     Ok(Json(results))
 }
 
-pub fn get_uuid(
-    in_group_id: i32,
+/// Default weights of the members of a group that have one, by uuid
+pub fn get_member_weights(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<HashMap<String, BigDecimal>, anyhow::Error> {
+    let weights = group_members::table
+        .select((group_members::uuid, group_members::weight.assume_not_null()))
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::weight.is_not_null())
+        .load::<(String, BigDecimal)>(conn)?;
+    Ok(weights.into_iter().collect())
+}
+
+/// Server version of `member`: the member with the same uuid, or else the one
+/// holding its nickname
+pub fn find_group_member(
+    group_id: i32,
+    member: &GroupMember,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<GroupMember>, anyhow::Error> {
+    let current = group_members::table
+        .select(GroupMember::as_select())
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(&member.uuid))
+        .get_result::<GroupMember>(conn)
+        .optional()?;
+    match current {
+        Some(current) => Ok(Some(current)),
+        None => find_nickname_holder(group_id, &member.uuid, &member.nickname, conn),
+    }
+}
+
+/// Member of the group other than `uuid` holding `nickname`, compared with
+/// `nickname_key`
+fn find_nickname_holder(
+    group_id: i32,
+    uuid: &str,
     nickname: &str,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-) -> Result<String, anyhow::Error> {
-    group_members::table
-        .select(group_members::uuid)
-        .filter(group_members::group_id.eq(in_group_id))
-        .filter(group_members::nickname.eq(nickname))
-        .get_result::<String>(conn)
-        .map_err(|v| anyhow!(v))
+) -> Result<Option<GroupMember>, anyhow::Error> {
+    Ok(group_members::table
+        .select(GroupMember::as_select())
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::nickname_key.eq(nickname_key(nickname)))
+        .filter(group_members::uuid.ne(uuid))
+        .get_result::<GroupMember>(conn)
+        .optional()?)
 }
 
-pub fn add_group_members(
+/// Insert or update a member, the most recent `modified_at` wins.
+/// A nickname already used by another member of the group, compared with
+/// `nickname_key`, is a conflict.
+pub fn add_group_member(
     group_id: i32,
-    members: Vec<GroupMember>,
+    member: GroupMember,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-) -> Result<(), anyhow::Error> {
+) -> Result<SyncStatus, anyhow::Error> {
     #[derive(Insertable, AsChangeset, Debug)]
     #[diesel(table_name = group_members)]
     pub struct NewGroupMember {
         uuid: String,
         group_id: i32,
         nickname: String,
+        nickname_key: String,
         user_id: Option<i32>,
         modified_at: NaiveDateTime,
+        weight: Option<BigDecimal>,
     }
     use unicode_truncate::UnicodeTruncateStr;
 
-    for member in members {
-        let new_member = NewGroupMember {
-            group_id,
-            modified_at: member.modified_at,
-            nickname: member.nickname.as_str().unicode_truncate(MAX_MEMBER_NAME_SIZE).0.to_string(),
-            user_id: None,
-            uuid: member.uuid,
-        };
+    let nickname = member
+        .nickname
+        .as_str()
+        .unicode_truncate(MAX_MEMBER_NAME_SIZE)
+        .0
+        .to_string();
+    let new_member = NewGroupMember {
+        group_id,
+        modified_at: member.modified_at,
+        nickname_key: nickname_key(&nickname),
+        nickname,
+        user_id: None,
+        uuid: member.uuid,
+        weight: member.weight,
+    };
 
-        //check unicity
-        if let Ok(_uuid) = get_uuid(group_id, &new_member.nickname, conn) {
-            continue;
-        }
+    // An outdated change is stale even if its nickname is now taken
+    if is_deleted(
+        TombstoneEntity::GroupMember,
+        &new_member.uuid,
+        new_member.modified_at,
+        conn,
+    )? {
+        return Ok(SyncStatus::Stale);
+    }
+    // Compared by the database, which stores timestamps to the microsecond
+    let outdated = diesel::select(diesel::dsl::exists(
+        group_members::table
+            .filter(group_members::uuid.eq(&new_member.uuid))
+            .filter(group_members::modified_at.ge(new_member.modified_at)),
+    ))
+    .get_result::<bool>(conn)?;
+    if outdated {
+        return Ok(SyncStatus::Stale);
+    }
+
+    //check unicity
+    if find_nickname_holder(group_id, &new_member.uuid, &new_member.nickname, conn)?.is_some() {
+        return Ok(SyncStatus::Conflict);
+    }
+
+    let previous_nickname = group_members::table
+        .select(group_members::nickname)
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(&new_member.uuid))
+        .get_result::<String>(conn)
+        .optional()?;
 
+    let applied = {
         use diesel::query_dsl::methods::FilterDsl;
         use diesel::upsert::excluded;
         diesel::insert_into(group_members::table)
@@ -137,37 +258,264 @@ pub fn add_group_members(
                 group_members::group_id.eq(group_id),
                 group_members::modified_at.eq(&new_member.modified_at),
                 group_members::nickname.eq(&new_member.nickname),
+                group_members::nickname_key.eq(&new_member.nickname_key),
                 group_members::uuid.eq(&new_member.uuid),
+                // Kept when left out, as by clients predating weights
+                new_member
+                    .weight
+                    .as_ref()
+                    .map(|weight| group_members::weight.eq(weight)),
             ))
             .filter(group_members::modified_at.lt(excluded(group_members::modified_at)))
-            .returning(GroupMember::as_returning())
-            .execute(conn)?;
+            .execute(conn)?
+    };
+    if applied == 0 {
+        return Ok(SyncStatus::Stale);
+    }
+
+    if let Some(previous_nickname) = previous_nickname {
+        record_rename(
+            group_id,
+            &new_member.uuid,
+            &previous_nickname,
+            &new_member.nickname,
+            new_member.modified_at,
+            conn,
+        )?;
     }
+    Ok(SyncStatus::Applied)
+}
+
+/// Nickname as compared for uniqueness of the members: ignoring case and the
+/// Unicode form, so that "José" typed on two keyboards is the same name
+pub fn nickname_key(nickname: &str) -> String {
+    nickname.trim().nfkc().collect::<String>().to_lowercase()
+}
+
+/// Previous nickname of a member
+#[derive(Queryable, Selectable, Debug, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::member_renames)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MemberRename {
+    pub member_uuid: String,
+    pub old_nickname: String,
+    pub new_nickname: String,
+    pub renamed_at: NaiveDateTime,
+}
+
+/// Keep track of a nickname change, nothing if the nickname did not change
+fn record_rename(
+    group_id: i32,
+    member_uuid: &str,
+    old_nickname: &str,
+    new_nickname: &str,
+    renamed_at: NaiveDateTime,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    if old_nickname == new_nickname {
+        return Ok(());
+    }
+    diesel::insert_into(member_renames::table)
+        .values((
+            member_renames::group_id.eq(group_id),
+            member_renames::member_uuid.eq(member_uuid),
+            member_renames::old_nickname.eq(old_nickname),
+            member_renames::new_nickname.eq(new_nickname),
+            member_renames::renamed_at.eq(renamed_at),
+        ))
+        .execute(conn)?;
     Ok(())
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RenameQuery {
+    pub nickname: String,
+}
+
+/// Rename the member `member_uuid`, or return the other member already
+/// holding the nickname
+pub fn rename_group_member(
+    group_id: i32,
+    member_uuid: &str,
+    nickname: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Result<GroupMember, GroupMember>, anyhow::Error> {
+    use unicode_truncate::UnicodeTruncateStr;
+    let nickname = nickname.trim().unicode_truncate(MAX_MEMBER_NAME_SIZE).0;
+    if nickname.is_empty() {
+        return Err(ApiError::InvalidInput.into());
+    }
+    let member = group_members::table
+        .select(GroupMember::as_select())
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .get_result::<GroupMember>(conn)?;
+
+    if let Some(holder) = find_nickname_holder(group_id, &member.uuid, nickname, conn)? {
+        return Ok(Err(holder));
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let renamed = diesel::update(group_members::table)
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .set((
+            group_members::nickname.eq(nickname),
+            group_members::nickname_key.eq(nickname_key(nickname)),
+            group_members::modified_at.eq(now),
+        ))
+        .returning(GroupMember::as_returning())
+        .get_result::<GroupMember>(conn)?;
+    record_rename(group_id, member_uuid, &member.nickname, nickname, now, conn)?;
+    Ok(Ok(renamed))
+}
+
+///groups/{token_id}/group_members/{member_uuid}/rename
+/// CONFLICT with the member holding the nickname, compared with `nickname_key`.
+pub async fn handler_rename_group_member(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: Option<AuthUser>,
+    Json(query): Json<RenameQuery>,
+) -> Result<Json<GroupMember>, AppError<GroupMember>> {
+    let mut conn = state_server.pool.get()?;
+    let renamed = conn
+        .transaction::<Result<GroupMember, GroupMember>, anyhow::Error, _>(|conn| {
+            let group_id = get_group_id(&token, conn)?;
+            require_role(group_id, user, Role::Editor, conn)?;
+            rename_group_member(group_id, &member_uuid, &query.nickname, conn)
+        })
+        .map_err(AppError::from)?;
+    let member =
+        renamed.map_err(|holder| AppError::with_content(ApiError::NicknameTaken, holder))?;
+    state_server.notify(
+        &token,
+        GroupEventKind::Updated,
+        TombstoneEntity::GroupMember,
+        &member.uuid,
+    );
+
+    Ok(Json(member))
+}
+
+///groups/{token_id}/member_renames
+/// Every rename of the members of the group, oldest first.
+pub async fn handler_member_renames(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+) -> Result<Json<Vec<MemberRename>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+
+    let results = member_renames::table
+        .select(MemberRename::as_select())
+        .filter(member_renames::group_id.eq(group_id))
+        .order((member_renames::renamed_at, member_renames::id))
+        .load::<MemberRename>(&mut conn)?;
+
+    Ok(Json(results))
+}
+
+/// Returns the uuids of the members actually saved, or the member holding
+/// the nickname of one of them
+pub fn add_group_members(
+    group_id: i32,
+    members: Vec<GroupMember>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Result<Vec<String>, GroupMember>, anyhow::Error> {
+    let mut applied = vec![];
+    for member in members {
+        let uuid = member.uuid.clone();
+        let nickname = member.nickname.clone();
+        match add_group_member(group_id, member, conn)? {
+            SyncStatus::Applied => applied.push(uuid),
+            SyncStatus::Conflict => {
+                let holder = find_nickname_holder(group_id, &uuid, &nickname, conn)?
+                    .ok_or(diesel::NotFound)?;
+                return Ok(Err(holder));
+            }
+            _ => {}
+        }
+    }
+    Ok(Ok(applied))
+}
+
+/// CONFLICT with the member holding the nickname of one of the members,
+/// compared with `nickname_key`: none of them is saved.
 pub async fn handler_add_group_members(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    user: Option<AuthUser>,
     Json(members): Json<Vec<GroupMember>>,
-) -> Result<Json<Vec<GroupMember>>, AppError> {
+) -> Result<Json<Vec<GroupMember>>, AppError<GroupMember>> {
+    let mut conn = state_server.pool.get()?;
+    let mut holder = None;
+    let (applied, result) = conn
+        .transaction::<(Vec<String>, Vec<GroupMember>), anyhow::Error, _>(|conn| {
+            let group_id = get_group_id(&token, conn)?;
+            require_role(group_id, user, Role::Editor, conn)?;
+            // An error rolls back the members saved before the conflict
+            let applied = add_group_members(group_id, members, conn)?.map_err(|member| {
+                holder = Some(member);
+                ApiError::NicknameTaken
+            })?;
 
+            Ok((applied, get_group_members(group_id, None, conn)?))
+        })
+        .map_err(|error| match holder.take() {
+            Some(holder) => AppError::with_content(ApiError::NicknameTaken, holder),
+            None => AppError::from(error),
+        })?;
+    for uuid in applied {
+        state_server.notify(
+            &token,
+            GroupEventKind::Updated,
+            TombstoneEntity::GroupMember,
+            &uuid,
+        );
+    }
+
+    Ok(Json(result))
+}
+
+/// v2/groups/{token_id}/group_members
+pub async fn handler_add_group_members_v2(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+    Json(members): Json<Vec<GroupMember>>,
+) -> Result<Json<Vec<ItemResult<GroupMember>>>, AppError> {
     let mut conn = state_server.pool.get()?;
     let result = conn
-        .transaction::<Vec<GroupMember>, anyhow::Error, _>(|conn| {
+        .transaction::<Vec<ItemResult<GroupMember>>, anyhow::Error, _>(|conn| {
             let group_id = get_group_id(&token, conn)?;
-            add_group_members(group_id, members, conn)?;
+            require_role(group_id, user, Role::Editor, conn)?;
+            let mut results = vec![];
+            for member in members {
+                let status = add_group_member(group_id, member.clone(), conn)?;
+                let current = find_group_member(group_id, &member, conn)?;
+                results.push(ItemResult::new(&member.uuid, status, current));
+            }
 
-            get_all_members(&token, conn)
+            Ok(results)
         })
         .map_err(AppError::from)?;
+    for member in result.iter().filter(|r| r.status == SyncStatus::Applied) {
+        state_server.notify(
+            &token,
+            GroupEventKind::Updated,
+            TombstoneEntity::GroupMember,
+            &member.uuid,
+        );
+    }
 
     Ok(Json(result))
 }
 
-use crate::entrypoint::transactions::{get_transaction_debt, get_transaction_paid_by};
-
-use bigdecimal::num_traits::Zero;
+use crate::entrypoint::transactions::{
+    get_member_transactions, get_paid_transactions, release_member_transactions,
+    ReleasedTransactions,
+};
 
 pub fn get_member_id(
     group_id: i32,
@@ -183,36 +531,505 @@ pub fn get_member_id(
     Ok(member_result)
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberDeleteStatus {
+    Deleted,
+    /// The member paid or owes a share of transactions, listed with the result.
+    BlockedByDebts,
+    /// The member paid the listed transactions: a forced deletion needs
+    /// `reassign_to`, taking them out would change the other balances.
+    BlockedByPayments,
+    /// The member was modified after the deletion.
+    Stale,
+    NotFound,
+}
+
+/// Outcome of the deletion of one member
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MemberDeleteResult {
+    pub uuid: String,
+    pub status: MemberDeleteStatus,
+    /// Transactions blocking the deletion, or changed by a forced one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<String>,
+}
+
+impl MemberDeleteResult {
+    fn new(uuid: &str, status: MemberDeleteStatus, transactions: Vec<String>) -> Self {
+        Self {
+            uuid: uuid.to_string(),
+            status,
+            transactions,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct DeleteMembersQuery {
+    /// Delete the members with debts or payments anyway
+    #[serde(default)]
+    pub force: bool,
+    /// Member taking over the payments and shares of the members deleted by
+    /// force. Without it their shares are taken out of the transactions, the
+    /// payers bearing them, and members who paid a transaction are kept.
+    #[serde(default)]
+    pub reassign_to: Option<String>,
+}
+
+/// Delete a member, leaving a tombstone behind. A member with debts or
+/// payments is only deleted with `force`, after releasing its transactions
+/// to the member `reassign_to`.
+pub fn remove_group_member(
+    group_id: i32,
+    member: &GroupMember,
+    force: bool,
+    reassign_to: Option<i32>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(MemberDeleteResult, ReleasedTransactions), anyhow::Error> {
+    let Ok(member_id) = get_member_id(group_id, member.uuid.clone(), conn) else {
+        // Deleting twice is not an error
+        let status = match get_tombstone(TombstoneEntity::GroupMember, &member.uuid, conn)? {
+            Some(_) => MemberDeleteStatus::Deleted,
+            None => MemberDeleteStatus::NotFound,
+        };
+        return Ok((
+            MemberDeleteResult::new(&member.uuid, status, vec![]),
+            ReleasedTransactions::default(),
+        ));
+    };
+
+    let blocking = get_member_transactions(group_id, member_id, conn)?;
+    if !blocking.is_empty() && !force {
+        return Ok((
+            MemberDeleteResult::new(&member.uuid, MemberDeleteStatus::BlockedByDebts, blocking),
+            ReleasedTransactions::default(),
+        ));
+    }
+    if !blocking.is_empty() && reassign_to.is_none() {
+        let paid = get_paid_transactions(group_id, member_id, conn)?;
+        if !paid.is_empty() {
+            return Ok((
+                MemberDeleteResult::new(&member.uuid, MemberDeleteStatus::BlockedByPayments, paid),
+                ReleasedTransactions::default(),
+            ));
+        }
+    }
+
+    // Compared by the database, which stores timestamps to the microsecond
+    let outdated = diesel::select(diesel::dsl::exists(
+        group_members::table
+            .filter(group_members::id.eq(member_id))
+            .filter(group_members::modified_at.lt(member.modified_at)),
+    ))
+    .get_result::<bool>(conn)?;
+    if !outdated {
+        return Ok((
+            MemberDeleteResult::new(&member.uuid, MemberDeleteStatus::Stale, vec![]),
+            ReleasedTransactions::default(),
+        ));
+    }
+
+    let released = if blocking.is_empty() {
+        ReleasedTransactions::default()
+    } else {
+        release_member_transactions(group_id, member_id, reassign_to, conn)?
+    };
+
+    let had_owner = has_owner(group_id, conn)?;
+    diesel::delete(group_members::table)
+        .filter(group_members::id.eq(member_id))
+        .execute(conn)?;
+    // An owned group keeps at least one owner, the caller rolls back
+    if had_owner && !has_owner(group_id, conn)? {
+        return Err(ApiError::LastOwner.into());
+    }
+    add_tombstone(
+        group_id,
+        TombstoneEntity::GroupMember,
+        &member.uuid,
+        member.modified_at,
+        conn,
+    )?;
+    let changed = released
+        .updated
+        .iter()
+        .chain(&released.deleted)
+        .cloned()
+        .collect();
+    Ok((
+        MemberDeleteResult::new(&member.uuid, MemberDeleteStatus::Deleted, changed),
+        released,
+    ))
+}
+
+/// Delete a member without debts nor payments, leaving a tombstone behind
+pub fn delete_group_member(
+    group_id: i32,
+    member: &GroupMember,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<SyncStatus, anyhow::Error> {
+    let (result, _) = remove_group_member(group_id, member, false, None, conn)?;
+    Ok(match result.status {
+        MemberDeleteStatus::Deleted => SyncStatus::Applied,
+        MemberDeleteStatus::BlockedByDebts | MemberDeleteStatus::BlockedByPayments => {
+            SyncStatus::Conflict
+        }
+        MemberDeleteStatus::Stale => SyncStatus::Stale,
+        MemberDeleteStatus::NotFound => SyncStatus::Invalid,
+    })
+}
+
+/// Members with debts or payments are left in place unless `force` is set,
+/// see `DeleteMembersQuery`.
 pub async fn handler_delete_group_members(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    Query(query): Query<DeleteMembersQuery>,
+    user: Option<AuthUser>,
     Json(members): Json<Vec<GroupMember>>,
+) -> Result<Json<Vec<MemberDeleteResult>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let (results, released) = conn
+        .transaction::<(Vec<MemberDeleteResult>, ReleasedTransactions), anyhow::Error, _>(|conn| {
+            let group_id = get_group_id(&token, conn)?;
+            require_role(group_id, user, Role::Owner, conn)?;
+            let reassign_to = match &query.reassign_to {
+                Some(uuid) if members.iter().any(|member| &member.uuid == uuid) => {
+                    return Err(ApiError::InvalidInput.into());
+                }
+                Some(uuid) => Some(
+                    group_members::table
+                        .select(group_members::id)
+                        .filter(group_members::group_id.eq(group_id))
+                        .filter(group_members::uuid.eq(uuid))
+                        .get_result::<i32>(conn)
+                        .optional()?
+                        .ok_or(ApiError::InvalidInput)?,
+                ),
+                None => None,
+            };
+
+            let mut results = vec![];
+            let mut released = ReleasedTransactions::default();
+            for member in members {
+                let (result, member_released) =
+                    remove_group_member(group_id, &member, query.force, reassign_to, conn)?;
+                released.updated.extend(member_released.updated);
+                released.deleted.extend(member_released.deleted);
+                results.push(result);
+            }
+
+            Ok((results, released))
+        })
+        .map_err(AppError::from)?;
+
+    let deleted_members = results
+        .iter()
+        .filter(|result| result.status == MemberDeleteStatus::Deleted)
+        .map(|result| {
+            (
+                GroupEventKind::Deleted,
+                TombstoneEntity::GroupMember,
+                &result.uuid,
+            )
+        });
+    let updated = released
+        .updated
+        .iter()
+        .filter(|uuid| !released.deleted.contains(uuid))
+        .map(|uuid| (GroupEventKind::Updated, TombstoneEntity::Transaction, uuid));
+    let deleted = released
+        .deleted
+        .iter()
+        .map(|uuid| (GroupEventKind::Deleted, TombstoneEntity::Transaction, uuid));
+    for (kind, entity, uuid) in updated.chain(deleted).chain(deleted_members) {
+        state_server.notify(&token, kind, entity, uuid);
+    }
+
+    Ok(Json(results))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MergeMembersQuery {
+    /// Uuid of the duplicate member, deleted by the merge
+    pub duplicate: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MergeMembersResponse {
+    /// The member the duplicate was merged into
+    pub group_member: GroupMember,
+    /// Transactions changed by the merge
+    pub transactions: Vec<String>,
+}
+
+/// Merge the member `duplicate_uuid` into the member `member_uuid`: its
+/// payments and shares move over, its claim too if the member has none, and
+/// its uuid is left redirecting to the member.
+pub fn merge_group_members(
+    group_id: i32,
+    member_uuid: &str,
+    duplicate_uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(GroupMember, ReleasedTransactions), anyhow::Error> {
+    if member_uuid == duplicate_uuid {
+        return Err(ApiError::InvalidInput.into());
+    }
+    let (member_id, member_user) = group_members::table
+        .select((group_members::id, group_members::user_id))
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .get_result::<(i32, Option<i32>)>(conn)?;
+    let (duplicate_id, duplicate_user, duplicate_role) = group_members::table
+        .select((
+            group_members::id,
+            group_members::user_id,
+            group_members::role,
+        ))
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(duplicate_uuid))
+        .get_result::<(i32, Option<i32>, Role)>(conn)?;
+    if member_user.is_some() && duplicate_user.is_some() {
+        return Err(ApiError::MemberAlreadyClaimed.into());
+    }
+
+    let released = release_member_transactions(group_id, duplicate_id, Some(member_id), conn)?;
+    diesel::delete(group_members::table)
+        .filter(group_members::id.eq(duplicate_id))
+        .execute(conn)?;
+    let now = chrono::Utc::now().naive_utc();
+    add_redirect(
+        group_id,
+        TombstoneEntity::GroupMember,
+        duplicate_uuid,
+        member_uuid,
+        now,
+        conn,
+    )?;
+
+    if duplicate_user.is_some() {
+        diesel::update(group_members::table)
+            .filter(group_members::id.eq(member_id))
+            .set((
+                group_members::user_id.eq(duplicate_user),
+                group_members::role.eq(duplicate_role),
+            ))
+            .execute(conn)?;
+    }
+    let member = diesel::update(group_members::table)
+        .filter(group_members::id.eq(member_id))
+        .set(group_members::modified_at.eq(now))
+        .returning(GroupMember::as_returning())
+        .get_result::<GroupMember>(conn)?;
+    Ok((member, released))
+}
+
+///groups/{token_id}/group_members/{member_uuid}/merge
+/// For a person created twice, typically by two offline devices.
+pub async fn handler_merge_group_members(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: Option<AuthUser>,
+    Json(query): Json<MergeMembersQuery>,
+) -> Result<Json<MergeMembersResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let (member, released) = conn
+        .transaction::<(GroupMember, ReleasedTransactions), anyhow::Error, _>(|conn| {
+            let group_id = get_group_id(&token, conn)?;
+            require_role(group_id, user, Role::Owner, conn)?;
+            merge_group_members(group_id, &member_uuid, &query.duplicate, conn)
+        })
+        .map_err(AppError::from)?;
+
+    let events = [
+        (
+            GroupEventKind::Deleted,
+            TombstoneEntity::GroupMember,
+            &query.duplicate,
+        ),
+        (
+            GroupEventKind::Updated,
+            TombstoneEntity::GroupMember,
+            &member.uuid,
+        ),
+    ]
+    .into_iter()
+    .chain(
+        released
+            .updated
+            .iter()
+            .map(|uuid| (GroupEventKind::Updated, TombstoneEntity::Transaction, uuid)),
+    )
+    .chain(
+        released
+            .deleted
+            .iter()
+            .map(|uuid| (GroupEventKind::Deleted, TombstoneEntity::Transaction, uuid)),
+    );
+    for (kind, entity, uuid) in events {
+        state_server.notify(&token, kind, entity, uuid);
+    }
+
+    let transactions = released
+        .updated
+        .into_iter()
+        .chain(released.deleted)
+        .collect();
+    Ok(Json(MergeMembersResponse {
+        group_member: member,
+        transactions,
+    }))
+}
+
+/// Archive or restore the member `member_uuid`
+fn set_archived(
+    token: &str,
+    member_uuid: &str,
+    archived: bool,
+    user: Option<AuthUser>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<GroupMember, anyhow::Error> {
+    let group_id = get_group_id(token, conn)?;
+    require_role(group_id, user, Role::Editor, conn)?;
+    Ok(diesel::update(group_members::table)
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .set((
+            group_members::archived.eq(archived),
+            group_members::modified_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .returning(GroupMember::as_returning())
+        .get_result::<GroupMember>(conn)?)
+}
+
+///groups/{token_id}/group_members/{member_uuid}/archive
+/// The member stays in the transactions and balances of the group.
+pub async fn handler_archive_group_member(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: Option<AuthUser>,
+) -> Result<Json<GroupMember>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let member = set_archived(&token, &member_uuid, true, user, &mut conn)?;
+    state_server.notify(
+        &token,
+        GroupEventKind::Updated,
+        TombstoneEntity::GroupMember,
+        &member.uuid,
+    );
+
+    Ok(Json(member))
+}
+
+pub async fn handler_restore_group_member(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: Option<AuthUser>,
+) -> Result<Json<GroupMember>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let member = set_archived(&token, &member_uuid, false, user, &mut conn)?;
+    state_server.notify(
+        &token,
+        GroupEventKind::Updated,
+        TombstoneEntity::GroupMember,
+        &member.uuid,
+    );
+
+    Ok(Json(member))
+}
+
+/// Link the member `member_uuid` to the logged-in user. A user holds at most
+/// one member per group and a member belongs to at most one user.
+/// The first user to claim a member of a group without owner becomes its owner.
+pub(crate) fn claim_group_member(
+    group_id: i32,
+    member_uuid: &str,
+    user_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    let (owner, stored_role) = group_members::table
+        .select((group_members::user_id, group_members::role))
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .get_result::<(Option<i32>, Role)>(conn)?;
+    match owner {
+        Some(owner) if owner == user_id => return Ok(()),
+        Some(_) => return Err(ApiError::MemberAlreadyClaimed.into()),
+        None => {}
+    }
+
+    let already_claimed = diesel::select(diesel::dsl::exists(
+        group_members::table
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::user_id.eq(user_id)),
+    ))
+    .get_result::<bool>(conn)?;
+    if already_claimed {
+        return Err(ApiError::MemberAlreadyClaimed.into());
+    }
+
+    // A member demoted by an owner stays so whoever claims it next
+    let role = if has_owner(group_id, conn)? {
+        stored_role.min(Role::Editor)
+    } else {
+        Role::Owner
+    };
+    diesel::update(group_members::table)
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::uuid.eq(member_uuid))
+        .set((
+            group_members::user_id.eq(user_id),
+            group_members::role.eq(role),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+///groups/{token_id}/group_members/{member_uuid}/claim
+pub async fn handler_claim_group_member(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: AuthUser,
 ) -> Result<(), AppError> {
     let mut conn = state_server.pool.get()?;
     conn.transaction::<(), anyhow::Error, _>(|conn| {
         let group_id = get_group_id(&token, conn)?;
-        for member in members {
-            if let Ok(member_id) = get_member_id(group_id, member.uuid, conn) {
-                let transaction_debts = get_transaction_debt(group_id, member_id, conn)?;
-                let has_debt = transaction_debts
-                    .iter()
-                    .any(|(_id, number)| !number.is_zero());
-
-                let transaction_paid = get_transaction_paid_by(group_id, member_id, conn)?;
-                let has_paid = transaction_paid.iter().any(|tr| !tr.amount.is_zero());
-
-                if !has_debt && !has_paid {
-                    diesel::delete(group_members::table)
-                        .filter(group_members::id.eq(member_id))
-                        .filter(group_members::modified_at.lt(member.modified_at))
-                        .execute(conn)?;
-                }
-            }
-        }
+        claim_group_member(group_id, &member_uuid, user.id, conn)
+    })
+    .map_err(AppError::from)
+}
 
+/// Give back a member claimed by the logged-in user. The last owner of a
+/// group cannot leave it without an owner.
+pub async fn handler_release_group_member(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: AuthUser,
+) -> Result<(), AppError> {
+    let mut conn = state_server.pool.get()?;
+    conn.transaction::<(), anyhow::Error, _>(|conn| {
+        let group_id = get_group_id(&token, conn)?;
+        let role = group_members::table
+            .select(group_members::role)
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::uuid.eq(&member_uuid))
+            .filter(group_members::user_id.eq(user.id))
+            .get_result::<Role>(conn)?;
+        let had_owner = has_owner(group_id, conn)?;
+        // Only the owner role is given up, a viewer stays one
+        diesel::update(group_members::table)
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::uuid.eq(&member_uuid))
+            .set((
+                group_members::user_id.eq(None::<i32>),
+                group_members::role.eq(role.min(Role::Editor)),
+            ))
+            .execute(conn)?;
+        if had_owner && !has_owner(group_id, conn)? {
+            return Err(ApiError::LastOwner.into());
+        }
         Ok(())
     })
-    .map_err(AppError::from)?;
-
-    Ok(())
+    .map_err(AppError::from)
 }
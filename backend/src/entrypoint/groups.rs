@@ -1,4 +1,11 @@
-use crate::entrypoint::AppError;
+use crate::auth::AuthUser;
+use crate::entrypoint::events::GroupEventKind;
+use crate::entrypoint::roles::{require_role, Role};
+use crate::entrypoint::share_links::{find_share_link, get_readable_group_id};
+use crate::entrypoint::sync::{ItemResult, SyncStatus};
+use crate::entrypoint::token_aliases::{current_token, find_token_alias};
+use crate::entrypoint::tombstones::{add_tombstone, get_tombstone, TombstoneEntity};
+use crate::entrypoint::{ApiError, AppError};
 use crate::schema::group_members;
 use crate::schema::groups;
 
@@ -37,6 +44,9 @@ impl GroupNoID {
     }
 }
 
+/// Id of the group owning `token_id`, or a previous token of it during its
+/// grace window. GONE if the group was deleted or the grace window is over.
+/// FORBIDDEN for a share link, which only opens the read routes.
 pub fn get_group_id(
     token_id: &str,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
@@ -44,9 +54,64 @@ pub fn get_group_id(
     let group_id = groups::table
         .select(groups::id)
         .filter(groups::token.eq(token_id))
-        .get_result::<i32>(conn)?;
+        .get_result::<i32>(conn)
+        .optional()?;
 
-    Ok(group_id)
+    if let Some(group_id) = group_id {
+        return Ok(group_id);
+    }
+    match find_token_alias(token_id, conn)? {
+        Some(group_id) => Ok(group_id),
+        None if find_share_link(token_id, conn)?.is_some() => Err(ApiError::ReadOnlyToken.into()),
+        None if get_tombstone(TombstoneEntity::Group, token_id, conn)?.is_some() => {
+            Err(ApiError::Gone.into())
+        }
+        None => Err(diesel::NotFound.into()),
+    }
+}
+
+/// Group owning `token_id` if it exists
+pub fn find_group(
+    token_id: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<GroupNoID>, anyhow::Error> {
+    let group = groups::table
+        .select(GroupNoID::as_select())
+        .filter(groups::token.eq(current_token(token_id, conn)?))
+        .get_result::<GroupNoID>(conn)
+        .optional()?;
+
+    Ok(group)
+}
+
+/// Id of the group `token_id` writes to, None if the token was never used:
+/// only such a token may create a group. FORBIDDEN for a share link, GONE
+/// for a deleted group or an expired previous token.
+pub fn find_writable_group_id(
+    token_id: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<i32>, anyhow::Error> {
+    match get_group_id(token_id, conn) {
+        Ok(group_id) => Ok(Some(group_id)),
+        Err(error) => match error.downcast_ref::<diesel::result::Error>() {
+            Some(diesel::result::Error::NotFound) => Ok(None),
+            _ => Err(error),
+        },
+    }
+}
+
+/// FORBIDDEN unless the caller has at least `role` in the group owning
+/// `token_id`. Anyone can create a group that does not exist yet.
+fn require_group_role(
+    token_id: &str,
+    user: Option<AuthUser>,
+    role: Role,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    match find_writable_group_id(token_id, conn)? {
+        Some(group_id) => require_role(group_id, user, role, conn),
+        None => Ok(()),
+    }
 }
 
 pub fn get_group(
@@ -61,6 +126,21 @@ pub fn get_group(
     Ok(group)
 }
 
+/// Groups where `user_id` claimed a member, with the group id and the uuid
+/// of the member
+pub fn get_user_groups(
+    user_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<(i32, GroupNoID, String)>, anyhow::Error> {
+    let results = groups::table
+        .inner_join(group_members::table.on(groups::id.eq(group_members::group_id)))
+        .filter(group_members::user_id.eq(user_id))
+        .select((groups::id, GroupNoID::as_select(), group_members::uuid))
+        .load::<(i32, GroupNoID, String)>(conn)?;
+
+    Ok(results)
+}
+
 //users/{user_id}/groups
 pub async fn handler_users_groups(
     State(state_server): State<state_server::StateServer>,
@@ -68,62 +148,83 @@ pub async fn handler_users_groups(
 ) -> Result<Json<Vec<GroupNoID>>, AppError> {
     let mut conn = state_server.pool.get()?;
 
-    let results = groups::table
-        .inner_join(group_members::table.on(groups::id.eq(group_members::group_id)))
-        .filter(group_members::user_id.eq(user_id))
-        .select(GroupNoID::as_select())
-        .load::<GroupNoID>(&mut conn)?;
+    let results = get_user_groups(user_id, &mut conn)?
+        .into_iter()
+        .map(|(_, group, _)| group)
+        .collect();
 
     Ok(Json(results))
 }
 
 ///groups/{token_id}
+/// A share link gets the group under its own token, so that it does not leak
+/// the edit token. A previous token gets the current one.
 pub async fn handler_groups(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
 ) -> Result<Json<GroupNoID>, AppError> {
     let mut conn = state_server.pool.get()?;
 
-    let results = groups::table
-        .select(GroupNoID::as_select())
-        .filter(groups::token.eq(token))
-        .first::<GroupNoID>(&mut conn)?;
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let mut results = get_group(group_id, &mut conn)?;
+    if find_share_link(&token, &mut conn)?.is_some() {
+        results.token = token;
+    }
     Ok(Json(results))
 }
 
-fn create_group(
+/// Insert or update a group, the most recent `modified_at` wins.
+/// A group pushed under a previous token keeps its current one. A share link
+/// or the token of a deleted group is refused rather than creating a group.
+pub fn upsert_group(
+    group_query: &GroupNoID,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-    group_query: GroupNoID,
-) -> Result<GroupNoID, AppError> {
-    #[derive(Queryable, PartialEq, Debug, Selectable, Serialize, Insertable, AsChangeset)]
-    struct Group {
-        name: String,
-        currency_id: String,
-        token: String,
+) -> Result<SyncStatus, anyhow::Error> {
+    #[derive(Debug, Insertable, AsChangeset)]
+    #[diesel(table_name = groups)]
+    struct Group<'a> {
+        name: &'a str,
+        currency_id: &'a str,
+        token: &'a str,
         created_at: NaiveDateTime,
         modified_at: NaiveDateTime,
     }
-    conn.transaction::<GroupNoID, anyhow::Error, _>(|conn| {
-        let to_insert = Group {
-            created_at: group_query.created_at,
-            currency_id: group_query.currency_id,
-            name: group_query.name,
-            token: group_query.token.clone(),
-            modified_at: group_query.modified_at,
-        };
-        use diesel::query_dsl::methods::FilterDsl;
-        use diesel::upsert::excluded;
-        let group_id = insert_into(groups::table)
-            .values(&to_insert)
-            .on_conflict(groups::token)
-            .do_update()
-            .set(&to_insert)
-            .filter(groups::modified_at.lt(excluded(groups::modified_at)))
-            .returning(groups::id)
-            .get_result::<i32>(conn)
-            .or(get_group_id(&group_query.token, conn))?;
-
-        get_group(group_id, conn)
+    find_writable_group_id(&group_query.token, conn)?;
+    let token = current_token(&group_query.token, conn)?;
+    let to_insert = Group {
+        created_at: group_query.created_at,
+        currency_id: &group_query.currency_id,
+        name: &group_query.name,
+        token: &token,
+        modified_at: group_query.modified_at,
+    };
+    use diesel::query_dsl::methods::FilterDsl;
+    use diesel::upsert::excluded;
+    let applied = insert_into(groups::table)
+        .values(&to_insert)
+        .on_conflict(groups::token)
+        .do_update()
+        .set(&to_insert)
+        .filter(groups::modified_at.lt(excluded(groups::modified_at)))
+        .execute(conn)?;
+
+    Ok(if applied > 0 {
+        SyncStatus::Applied
+    } else {
+        SyncStatus::Stale
+    })
+}
+
+fn create_group(
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+    group_query: GroupNoID,
+    user: Option<AuthUser>,
+) -> Result<(SyncStatus, GroupNoID), AppError> {
+    conn.transaction::<(SyncStatus, GroupNoID), anyhow::Error, _>(|conn| {
+        require_group_role(&group_query.token, user, Role::Editor, conn)?;
+        let status = upsert_group(&group_query, conn)?;
+        let group_id = get_group_id(&group_query.token, conn)?;
+        Ok((status, get_group(group_id, conn)?))
     })
     .map_err(AppError::from)
 }
@@ -131,58 +232,123 @@ fn create_group(
 ///groups
 pub async fn handler_create_group(
     State(state_server): State<state_server::StateServer>,
+    user: Option<AuthUser>,
     Json(group_query): Json<GroupNoID>,
 ) -> Result<Json<GroupNoID>, AppError> {
     let mut conn = state_server.pool.get()?;
-    let group = create_group(&mut conn, group_query);
+    let (status, group) = create_group(&mut conn, group_query, user)?;
+    if status == SyncStatus::Applied {
+        state_server.notify(
+            &group.token,
+            GroupEventKind::Updated,
+            TombstoneEntity::Group,
+            &group.token,
+        );
+    }
 
-    Ok(Json(group?))
+    Ok(Json(group))
 }
 
 pub async fn handler_create_groups(
     State(state_server): State<state_server::StateServer>,
+    user: Option<AuthUser>,
     Json(group_query): Json<Vec<GroupNoID>>,
-) -> Result<Json<Vec<GroupNoID>>, AppError> {
+) -> Result<Json<Vec<ItemResult<GroupNoID>>>, AppError> {
     let mut conn = state_server.pool.get()?;
     let mut results = vec![];
     for group in group_query {
-        results.push(create_group(&mut conn, group)?);
+        let result = conn
+            .transaction::<ItemResult<GroupNoID>, anyhow::Error, _>(|conn| {
+                require_group_role(&group.token, user, Role::Editor, conn)?;
+                let status = upsert_group(&group, conn)?;
+                let current = find_group(&group.token, conn)?;
+                Ok(ItemResult::new(&group.token, status, current))
+            })
+            .map_err(AppError::from)?;
+        if result.status == SyncStatus::Applied {
+            state_server.notify(
+                &group.token,
+                GroupEventKind::Updated,
+                TombstoneEntity::Group,
+                &group.token,
+            );
+        }
+        results.push(result);
     }
 
     Ok(Json(results))
 }
 
+/// Delete a group older than `group`, leaving a tombstone behind.
+/// Returns whether the group was deleted.
+fn delete_group(
+    group: &GroupNoID,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<bool, anyhow::Error> {
+    let token = current_token(&group.token, conn)?;
+    let deleted = diesel::delete(groups::table)
+        .filter(groups::modified_at.lt(group.modified_at))
+        .filter(groups::token.eq(&token))
+        .returning(groups::id)
+        .get_results::<i32>(conn)?;
+    for group_id in &deleted {
+        add_tombstone(
+            *group_id,
+            TombstoneEntity::Group,
+            &token,
+            group.modified_at,
+            conn,
+        )?;
+    }
+    Ok(!deleted.is_empty())
+}
+
 pub async fn handler_delete_groups(
     State(state_server): State<state_server::StateServer>,
+    user: Option<AuthUser>,
     Json(groups): Json<Vec<GroupNoID>>,
 ) -> Result<(), AppError> {
     for group in groups {
         let mut conn = state_server.pool.get()?;
 
-        conn.transaction::<(), anyhow::Error, _>(|conn| {
-            diesel::delete(groups::table)
-                .filter(groups::modified_at.lt(group.modified_at))
-                .filter(groups::token.eq(group.token))
-                .execute(conn)?;
-            Ok(())
-        })
-        .map_err(AppError::from)?;
+        let deleted = conn
+            .transaction::<bool, anyhow::Error, _>(|conn| {
+                require_group_role(&group.token, user, Role::Owner, conn)?;
+                delete_group(&group, conn)
+            })
+            .map_err(AppError::from)?;
+        if deleted {
+            state_server.notify(
+                &group.token,
+                GroupEventKind::Deleted,
+                TombstoneEntity::Group,
+                &group.token,
+            );
+        }
     }
     Ok(())
 }
 
 pub async fn handler_delete_group(
     State(state_server): State<state_server::StateServer>,
+    user: Option<AuthUser>,
     Json(group_query): Json<GroupNoID>,
 ) -> Result<(), AppError> {
     let mut conn = state_server.pool.get()?;
 
-    conn.transaction::<(), anyhow::Error, _>(|conn| {
-        diesel::delete(groups::table)
-            .filter(groups::modified_at.lt(group_query.modified_at))
-            .filter(groups::token.eq(group_query.token))
-            .execute(conn)?;
-        Ok(())
-    })
-    .map_err(AppError::from)
+    let deleted = conn
+        .transaction::<bool, anyhow::Error, _>(|conn| {
+            require_group_role(&group_query.token, user, Role::Owner, conn)?;
+            delete_group(&group_query, conn)
+        })
+        .map_err(AppError::from)?;
+    if deleted {
+        state_server.notify(
+            &group_query.token,
+            GroupEventKind::Deleted,
+            TombstoneEntity::Group,
+            &group_query.token,
+        );
+    }
+    Ok(())
 }
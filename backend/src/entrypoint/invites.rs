@@ -0,0 +1,216 @@
+use crate::auth::AuthUser;
+use crate::entrypoint::events::GroupEventKind;
+use crate::entrypoint::group_members::{
+    add_group_member, claim_group_member, find_group_member, GroupMember,
+};
+use crate::entrypoint::groups::{get_group, get_group_id, GroupNoID};
+use crate::entrypoint::roles::{require_role, Role};
+use crate::entrypoint::sync::SyncStatus;
+use crate::entrypoint::tombstones::TombstoneEntity;
+use crate::entrypoint::{ApiError, AppError};
+use crate::schema::invites;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use serde::{Deserialize, Serialize};
+
+/// Link for a logged-in user to join a group, until it expires or was used
+/// `max_uses` times
+#[derive(Queryable, Selectable, Debug, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::invites)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Invite {
+    pub token: String,
+    /// Member claimed on joining, created if needed, for a single-use invite
+    /// only. If None the invited user picks the nickname.
+    pub nickname: Option<String>,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct InviteQuery {
+    #[serde(default)]
+    pub nickname: Option<String>,
+    pub max_uses: i32,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct AcceptInviteQuery {
+    /// Ignored when the invite names the member
+    #[serde(default)]
+    pub nickname: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AcceptInviteResponse {
+    pub group: GroupNoID,
+    /// The member now claimed by the user
+    pub group_member: GroupMember,
+}
+
+///groups/{token_id}/invites
+pub async fn handler_invites(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+) -> Result<Json<Vec<Invite>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Owner, &mut conn)?;
+
+    let results = invites::table
+        .select(Invite::as_select())
+        .filter(invites::group_id.eq(group_id))
+        .order(invites::created_at)
+        .load::<Invite>(&mut conn)?;
+
+    Ok(Json(results))
+}
+
+pub async fn handler_create_invite(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+    Json(query): Json<InviteQuery>,
+) -> Result<Json<Invite>, AppError<String>> {
+    if query.max_uses < 1 {
+        return Err(AppError::with_content(
+            ApiError::InvalidInput,
+            "An invite needs at least one use".to_string(),
+        ));
+    }
+    // Every user accepting it would claim the same member
+    if query.nickname.is_some() && query.max_uses != 1 {
+        return Err(AppError::with_content(
+            ApiError::InvalidInput,
+            "An invite naming a member has a single use".to_string(),
+        ));
+    }
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Owner, &mut conn)?;
+
+    let invite = diesel::insert_into(invites::table)
+        .values((
+            invites::group_id.eq(group_id),
+            invites::token.eq(uuid::Uuid::new_v4().to_string()),
+            invites::nickname.eq(query.nickname.as_deref().map(str::trim)),
+            invites::max_uses.eq(query.max_uses),
+            invites::expires_at.eq(query.expires_at),
+            invites::created_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .returning(Invite::as_returning())
+        .get_result::<Invite>(&mut conn)?;
+
+    Ok(Json(invite))
+}
+
+///groups/{token_id}/invites/{invite_token}
+pub async fn handler_delete_invite(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, invite_token)): Path<(String, String)>,
+    user: Option<AuthUser>,
+) -> Result<(), AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Owner, &mut conn)?;
+
+    let deleted = diesel::delete(invites::table)
+        .filter(invites::group_id.eq(group_id))
+        .filter(invites::token.eq(&invite_token))
+        .execute(&mut conn)?;
+    if deleted == 0 {
+        return Err(diesel::NotFound.into());
+    }
+
+    Ok(())
+}
+
+/// Use the invite `invite_token` once, returning its group id and nickname.
+/// GONE if it expired or has no use left.
+fn use_invite(
+    invite_token: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(i32, Option<String>), anyhow::Error> {
+    let used = diesel::update(invites::table)
+        .filter(invites::token.eq(invite_token))
+        .filter(invites::uses.lt(invites::max_uses))
+        .filter(invites::expires_at.gt(chrono::Utc::now().naive_utc()))
+        .set(invites::uses.eq(invites::uses + 1))
+        .returning((invites::group_id, invites::nickname))
+        .get_result::<(i32, Option<String>)>(conn)
+        .optional()?;
+    if let Some(used) = used {
+        return Ok(used);
+    }
+
+    let exists = diesel::select(diesel::dsl::exists(
+        invites::table.filter(invites::token.eq(invite_token)),
+    ))
+    .get_result::<bool>(conn)?;
+    if exists {
+        Err(ApiError::Gone.into())
+    } else {
+        Err(diesel::NotFound.into())
+    }
+}
+
+///invites/{invite_token}/accept
+/// Join the group of the invite as the logged-in user: the member with the
+/// nickname is claimed, or created first if the group has none.
+pub async fn handler_accept_invite(
+    State(state_server): State<state_server::StateServer>,
+    Path(invite_token): Path<String>,
+    user: AuthUser,
+    Json(query): Json<AcceptInviteQuery>,
+) -> Result<Json<AcceptInviteResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let (response, created) = conn
+        .transaction::<(AcceptInviteResponse, bool), anyhow::Error, _>(|conn| {
+            let (group_id, nickname) = use_invite(&invite_token, conn)?;
+            let nickname = nickname.or(query.nickname).ok_or(ApiError::InvalidInput)?;
+
+            let mut member = GroupMember::new(nickname.trim());
+            let created = match find_group_member(group_id, &member, conn)? {
+                Some(existing) => {
+                    member = existing;
+                    false
+                }
+                None if add_group_member(group_id, member.clone(), conn)?
+                    == SyncStatus::Applied =>
+                {
+                    true
+                }
+                None => return Err(ApiError::NicknameTaken.into()),
+            };
+            claim_group_member(group_id, &member.uuid, user.id, conn)?;
+
+            let response = AcceptInviteResponse {
+                group: get_group(group_id, conn)?,
+                group_member: find_group_member(group_id, &member, conn)?
+                    .ok_or(diesel::NotFound)?,
+            };
+            Ok((response, created))
+        })
+        .map_err(AppError::from)?;
+    if created {
+        state_server.notify(
+            &response.group.token,
+            GroupEventKind::Updated,
+            TombstoneEntity::GroupMember,
+            &response.group_member.uuid,
+        );
+    }
+
+    Ok(Json(response))
+}
@@ -0,0 +1,177 @@
+use crate::auth::AuthUser;
+use crate::entrypoint::groups::get_group_id;
+use crate::entrypoint::{ApiError, AppError};
+use crate::schema::group_members;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+
+/// Role of a logged-in user in a group, held by the member they claimed.
+/// Roles are ordered: an owner can do everything an editor can.
+#[derive(
+    Deserialize,
+    Serialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Default,
+    AsExpression,
+    FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can only read the group.
+    Viewer,
+    /// Can add members and add or modify transactions.
+    #[default]
+    Editor,
+    /// Can also delete, and give roles to the other members.
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Role {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for Role {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            other => Err(format!("Unknown role {other}").into()),
+        }
+    }
+}
+
+pub fn has_owner(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<bool, anyhow::Error> {
+    Ok(diesel::select(diesel::dsl::exists(
+        group_members::table
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::user_id.is_not_null())
+            .filter(group_members::role.eq(Role::Owner)),
+    ))
+    .get_result::<bool>(conn)?)
+}
+
+/// Role of the caller in a group. A group without owner is open: every token
+/// holder has full rights. Once it has one, callers who did not claim a member
+/// of the group can only read it.
+pub fn get_role(
+    group_id: i32,
+    user: Option<AuthUser>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Role, anyhow::Error> {
+    if !has_owner(group_id, conn)? {
+        return Ok(Role::Owner);
+    }
+    let Some(user) = user else {
+        return Ok(Role::Viewer);
+    };
+    let role = group_members::table
+        .select(group_members::role)
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::user_id.eq(user.id))
+        .first::<Role>(conn)
+        .optional()?;
+    Ok(role.unwrap_or(Role::Viewer))
+}
+
+/// FORBIDDEN unless the caller has at least `role` in the group
+pub fn require_role(
+    group_id: i32,
+    user: Option<AuthUser>,
+    role: Role,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    if get_role(group_id, user, conn)? < role {
+        return Err(ApiError::Forbidden.into());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RoleQuery {
+    pub role: Role,
+}
+
+///groups/{token_id}/role
+pub async fn handler_get_role(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+) -> Result<Json<RoleQuery>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    let role = get_role(group_id, user, &mut conn)?;
+
+    Ok(Json(RoleQuery { role }))
+}
+
+///groups/{token_id}/group_members/{member_uuid}/role
+/// Only for claimed members, so that nobody can claim a role they were not given.
+pub async fn handler_set_role(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, member_uuid)): Path<(String, String)>,
+    user: AuthUser,
+    Json(query): Json<RoleQuery>,
+) -> Result<(), AppError> {
+    let mut conn = state_server.pool.get()?;
+    conn.transaction::<(), anyhow::Error, _>(|conn| {
+        let group_id = get_group_id(&token, conn)?;
+        require_role(group_id, Some(user), Role::Owner, conn)?;
+
+        let member_user_id = group_members::table
+            .select(group_members::user_id)
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::uuid.eq(&member_uuid))
+            .get_result::<Option<i32>>(conn)?;
+        if member_user_id.is_none() {
+            return Err(ApiError::Conflict.into());
+        }
+
+        let had_owner = has_owner(group_id, conn)?;
+        diesel::update(group_members::table)
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::uuid.eq(&member_uuid))
+            .set(group_members::role.eq(query.role))
+            .execute(conn)?;
+        // An owned group keeps at least one owner
+        if had_owner && !has_owner(group_id, conn)? {
+            return Err(ApiError::LastOwner.into());
+        }
+        Ok(())
+    })
+    .map_err(AppError::from)
+}
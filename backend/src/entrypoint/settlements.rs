@@ -0,0 +1,289 @@
+use crate::entrypoint::balances::get_balances;
+use crate::entrypoint::currencies::minor_unit;
+use crate::entrypoint::group_members::GroupMemberNoDate;
+use crate::entrypoint::groups::get_group;
+use crate::entrypoint::share_links::get_readable_group_id;
+use crate::entrypoint::AppError;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, RoundingMode, Signed, Zero};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Settlement {
+    pub from: GroupMemberNoDate,
+    pub to: GroupMemberNoDate,
+    pub amount: BigDecimal,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SettlementsResponse {
+    pub currency_id: String,
+    pub settlements: Vec<Settlement>,
+}
+
+/// Largest amount first, then by member uuid so that equal amounts always
+/// settle in the same order.
+fn compare_entries(
+    a: &(GroupMemberNoDate, BigDecimal),
+    b: &(GroupMemberNoDate, BigDecimal),
+) -> Ordering {
+    b.1.cmp(&a.1).then_with(|| a.0.uuid.cmp(&b.0.uuid))
+}
+
+/// Round every balance to `scale` decimal digits. The rounding residual is
+/// spread one minor unit at a time over the largest balances so that the
+/// rounded balances still sum to zero.
+fn round_balances(
+    balances: &[(GroupMemberNoDate, BigDecimal)],
+    scale: i64,
+) -> Vec<(GroupMemberNoDate, BigDecimal)> {
+    let mut rounded = balances
+        .iter()
+        .map(|(member, amount)| {
+            (
+                member.clone(),
+                amount.with_scale_round(scale, RoundingMode::HalfEven),
+            )
+        })
+        .collect::<Vec<_>>();
+    if rounded.is_empty() {
+        return rounded;
+    }
+
+    let residual = -rounded
+        .iter()
+        .fold(BigDecimal::zero(), |acc, (_, amount)| acc + amount);
+    let unit = BigDecimal::new(BigInt::from(1), scale);
+    let steps = (&residual / &unit).with_scale_round(0, RoundingMode::HalfEven);
+    let step = if residual.is_negative() { -&unit } else { unit };
+
+    let mut order = (0..rounded.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| {
+        rounded[b]
+            .1
+            .abs()
+            .cmp(&rounded[a].1.abs())
+            .then_with(|| rounded[a].0.uuid.cmp(&rounded[b].0.uuid))
+    });
+
+    let mut remaining = steps.abs();
+    let mut position = 0;
+    while remaining.is_positive() {
+        let index = order[position % order.len()];
+        rounded[index].1 += &step;
+        remaining -= 1;
+        position += 1;
+    }
+
+    rounded
+}
+
+/// Turn net balances (positive: is owed money, negative: owes money) into a
+/// list of transfers that settles every balance.
+///
+/// Balances that cancel each other exactly are paired first, then the
+/// largest debtor repeatedly pays the largest creditor. Amounts are rounded
+/// to `scale` decimal digits.
+pub fn compute_settlements(
+    balances: &[(GroupMemberNoDate, BigDecimal)],
+    scale: i64,
+) -> Vec<Settlement> {
+    let rounded = round_balances(balances, scale);
+
+    let mut creditors = rounded
+        .iter()
+        .filter(|(_, amount)| amount.is_positive())
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut debtors = rounded
+        .iter()
+        .filter(|(_, amount)| amount.is_negative())
+        .map(|(member, amount)| (member.clone(), -amount))
+        .collect::<Vec<_>>();
+    creditors.sort_by(compare_entries);
+    debtors.sort_by(compare_entries);
+
+    let mut settlements = vec![];
+
+    let mut index = 0;
+    while index < debtors.len() {
+        if let Some(position) = creditors
+            .iter()
+            .position(|(_, amount)| amount.eq(&debtors[index].1))
+        {
+            let (to, amount) = creditors.remove(position);
+            let (from, _) = debtors.remove(index);
+            settlements.push(Settlement { from, to, amount });
+        } else {
+            index += 1;
+        }
+    }
+
+    while !creditors.is_empty() && !debtors.is_empty() {
+        let amount = creditors[0].1.clone().min(debtors[0].1.clone());
+        settlements.push(Settlement {
+            from: debtors[0].0.clone(),
+            to: creditors[0].0.clone(),
+            amount: amount.clone(),
+        });
+        creditors[0].1 -= &amount;
+        debtors[0].1 -= &amount;
+
+        creditors.retain(|(_, amount)| amount.is_positive());
+        debtors.retain(|(_, amount)| amount.is_positive());
+        creditors.sort_by(compare_entries);
+        debtors.sort_by(compare_entries);
+    }
+
+    settlements
+}
+
+///groups/{token_id}/settlements
+pub async fn handler_get_settlements(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+) -> Result<Json<SettlementsResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let group = get_group(group_id, &mut conn)?;
+    let balances = get_balances(group_id, &mut conn)?
+        .into_iter()
+        .map(|balance| (balance.member, balance.balance))
+        .collect::<Vec<_>>();
+
+    Ok(Json(SettlementsResponse {
+        settlements: compute_settlements(&balances, minor_unit(&group.currency_id)),
+        currency_id: group.currency_id,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn member(name: &str) -> GroupMemberNoDate {
+        GroupMemberNoDate {
+            uuid: format!("uuid_{name}"),
+            nickname: name.to_string(),
+        }
+    }
+
+    fn balances(values: &[(&str, &str)]) -> Vec<(GroupMemberNoDate, BigDecimal)> {
+        values
+            .iter()
+            .map(|(name, amount)| (member(name), BigDecimal::from_str(amount).unwrap()))
+            .collect()
+    }
+
+    fn transfers(settlements: &[Settlement]) -> Vec<(String, String, String)> {
+        settlements
+            .iter()
+            .map(|s| {
+                (
+                    s.from.nickname.clone(),
+                    s.to.nickname.clone(),
+                    s.amount.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settled_group_has_no_transfer() {
+        let settlements = compute_settlements(&balances(&[("a", "0"), ("b", "0")]), 2);
+        assert!(settlements.is_empty());
+        assert!(compute_settlements(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn debtors_pay_largest_creditor_first() {
+        let settlements = compute_settlements(
+            &balances(&[("alice", "20"), ("bob", "-10"), ("carol", "-10")]),
+            2,
+        );
+        assert_eq!(
+            transfers(&settlements),
+            vec![
+                ("bob".into(), "alice".into(), "10.00".into()),
+                ("carol".into(), "alice".into(), "10.00".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn exact_matches_are_paired_first() {
+        let settlements = compute_settlements(
+            &balances(&[
+                ("alice", "50"),
+                ("bob", "30"),
+                ("carol", "-30"),
+                ("dave", "-50"),
+            ]),
+            2,
+        );
+        assert_eq!(
+            transfers(&settlements),
+            vec![
+                ("dave".into(), "alice".into(), "50.00".into()),
+                ("carol".into(), "bob".into(), "30.00".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rounding_keeps_the_sum_at_zero() {
+        let settlements = compute_settlements(
+            &balances(&[
+                ("alice", "6.666666"),
+                ("bob", "-3.333333"),
+                ("carol", "-3.333333"),
+            ]),
+            2,
+        );
+        let total = settlements
+            .iter()
+            .fold(BigDecimal::zero(), |acc, s| acc + &s.amount);
+        assert_eq!(total, BigDecimal::from_str("6.66").unwrap());
+        assert!(settlements
+            .iter()
+            .all(|s| s.amount.fractional_digit_count() <= 2));
+    }
+
+    #[test]
+    fn zero_decimal_currency() {
+        let settlements =
+            compute_settlements(&balances(&[("alice", "100.4"), ("bob", "-100.4")]), 0);
+        assert_eq!(
+            transfers(&settlements),
+            vec![("bob".into(), "alice".into(), "100".into())]
+        );
+    }
+
+    #[test]
+    fn ties_are_deterministic() {
+        let first = compute_settlements(
+            &balances(&[("b", "10"), ("a", "10"), ("d", "-10"), ("c", "-10")]),
+            2,
+        );
+        let second = compute_settlements(
+            &balances(&[("a", "10"), ("c", "-10"), ("b", "10"), ("d", "-10")]),
+            2,
+        );
+        assert_eq!(first, second);
+        assert_eq!(
+            transfers(&first),
+            vec![
+                ("c".into(), "a".into(), "10.00".into()),
+                ("d".into(), "b".into(), "10.00".into()),
+            ]
+        );
+    }
+}
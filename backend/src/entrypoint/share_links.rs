@@ -0,0 +1,117 @@
+use crate::auth::AuthUser;
+use crate::entrypoint::groups::get_group_id;
+use crate::entrypoint::roles::{require_role, Role};
+use crate::entrypoint::{ApiError, AppError};
+use crate::schema::share_links;
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use serde::{Deserialize, Serialize};
+
+/// Read-only token of a group: it opens the GET routes of the group, every
+/// other route refuses it.
+#[derive(Queryable, Selectable, Debug, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::share_links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ShareLink {
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+/// Share link holding `token` if it exists, with the id of its group
+pub fn find_share_link(
+    token: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<(i32, ShareLink)>, anyhow::Error> {
+    let share_link = share_links::table
+        .select((share_links::group_id, ShareLink::as_select()))
+        .filter(share_links::token.eq(token))
+        .get_result::<(i32, ShareLink)>(conn)
+        .optional()?;
+
+    Ok(share_link)
+}
+
+/// Id of the group `token` gives read access to: its edit token or one of
+/// its share links. GONE if the link was revoked.
+pub fn get_readable_group_id(
+    token: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<i32, anyhow::Error> {
+    match find_share_link(token, conn)? {
+        Some((_, share_link)) if share_link.revoked_at.is_some() => Err(ApiError::Gone.into()),
+        Some((group_id, _)) => Ok(group_id),
+        None => get_group_id(token, conn),
+    }
+}
+
+///groups/{token_id}/share_links
+pub async fn handler_share_links(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+) -> Result<Json<Vec<ShareLink>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Editor, &mut conn)?;
+
+    let results = share_links::table
+        .select(ShareLink::as_select())
+        .filter(share_links::group_id.eq(group_id))
+        .order(share_links::created_at)
+        .load::<ShareLink>(&mut conn)?;
+
+    Ok(Json(results))
+}
+
+pub async fn handler_create_share_link(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+) -> Result<Json<ShareLink>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Editor, &mut conn)?;
+
+    let share_link = diesel::insert_into(share_links::table)
+        .values((
+            share_links::group_id.eq(group_id),
+            share_links::token.eq(uuid::Uuid::new_v4().to_string()),
+            share_links::created_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .returning(ShareLink::as_returning())
+        .get_result::<ShareLink>(&mut conn)?;
+
+    Ok(Json(share_link))
+}
+
+///groups/{token_id}/share_links/{share_token}
+/// The link keeps answering GONE once revoked.
+pub async fn handler_revoke_share_link(
+    State(state_server): State<state_server::StateServer>,
+    Path((token, share_token)): Path<(String, String)>,
+    user: Option<AuthUser>,
+) -> Result<(), AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group_id = get_group_id(&token, &mut conn)?;
+    require_role(group_id, user, Role::Owner, &mut conn)?;
+
+    let revoked = diesel::update(share_links::table)
+        .filter(share_links::group_id.eq(group_id))
+        .filter(share_links::token.eq(&share_token))
+        .filter(share_links::revoked_at.is_null())
+        .set(share_links::revoked_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(&mut conn)?;
+    if revoked == 0 {
+        return Err(diesel::NotFound.into());
+    }
+
+    Ok(())
+}
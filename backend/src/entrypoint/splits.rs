@@ -0,0 +1,274 @@
+use crate::entrypoint::validation::{FieldError, FieldErrorCode};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, RoundingMode, Signed, Zero};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMode {
+    /// Every debtor amount is given by the client.
+    #[default]
+    Exact,
+    /// The amount is shared equally between the debtors, or by their default
+    /// weights when their members have one.
+    Equal,
+    /// The amount is shared in proportion to the weight of each debtor.
+    Shares,
+    /// Each debtor pays a percentage of the amount, percentages sum to 100.
+    Percentage,
+}
+
+impl SplitMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SplitMode::Exact => "exact",
+            SplitMode::Equal => "equal",
+            SplitMode::Shares => "shares",
+            SplitMode::Percentage => "percentage",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for SplitMode {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for SplitMode {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "exact" => Ok(SplitMode::Exact),
+            "equal" => Ok(SplitMode::Equal),
+            "shares" => Ok(SplitMode::Shares),
+            "percentage" => Ok(SplitMode::Percentage),
+            other => Err(format!("Unknown split mode {other}").into()),
+        }
+    }
+}
+
+/// Share `amount` between debtors proportionally to `weights`, each part
+/// rounded down to `scale` decimal digits. The remaining minor units go to
+/// the parts with the largest rounding loss, the first debtor winning ties,
+/// so that the parts always sum to `amount`.
+pub fn distribute(
+    amount: &BigDecimal,
+    weights: &[BigDecimal],
+    scale: i64,
+) -> Result<Vec<BigDecimal>, Vec<FieldError>> {
+    let negatives = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| weight.is_negative())
+        .map(|(i, _)| FieldError::new(format!("debtors[{i}].value"), FieldErrorCode::Negative))
+        .collect::<Vec<FieldError>>();
+    if !negatives.is_empty() {
+        return Err(negatives);
+    }
+    let total = weights
+        .iter()
+        .fold(BigDecimal::zero(), |acc, weight| acc + weight);
+    if total.is_zero() {
+        return Err(vec![FieldError::new("debtors", FieldErrorCode::ZeroSplit)]);
+    }
+
+    let scale = scale.max(amount.fractional_digit_count());
+    let unit = BigDecimal::new(BigInt::from(1), scale);
+
+    let exact = weights
+        .iter()
+        .map(|weight| amount * weight / &total)
+        .collect::<Vec<BigDecimal>>();
+    let mut parts = exact
+        .iter()
+        .map(|part| part.with_scale_round(scale, RoundingMode::Down))
+        .collect::<Vec<BigDecimal>>();
+
+    let distributed = parts
+        .iter()
+        .fold(BigDecimal::zero(), |acc, part| acc + part);
+    let remaining = ((amount - distributed) / &unit).with_scale_round(0, RoundingMode::HalfEven);
+
+    let mut order = (0..parts.len()).collect::<Vec<usize>>();
+    order.sort_by(
+        |&a, &b| match (&exact[b] - &parts[b]).cmp(&(&exact[a] - &parts[a])) {
+            Ordering::Equal => a.cmp(&b),
+            ordering => ordering,
+        },
+    );
+
+    let mut remaining = remaining;
+    let mut position = 0;
+    while remaining.is_positive() {
+        parts[order[position % order.len()]] += &unit;
+        remaining -= 1;
+        position += 1;
+    }
+
+    Ok(parts)
+}
+
+/// Compute the amount owed by each debtor from the split `values` given by
+/// the client. Returns `None` for the exact mode, where amounts are given as is.
+pub fn compute_split(
+    mode: SplitMode,
+    amount: &BigDecimal,
+    values: &[Option<BigDecimal>],
+    scale: i64,
+) -> Result<Option<Vec<BigDecimal>>, Vec<FieldError>> {
+    let weights: Vec<BigDecimal> = match mode {
+        SplitMode::Exact => return Ok(None),
+        SplitMode::Equal => values
+            .iter()
+            .map(|value| value.clone().unwrap_or_else(|| BigDecimal::from(1)))
+            .collect(),
+        SplitMode::Shares | SplitMode::Percentage => {
+            let missing = values
+                .iter()
+                .enumerate()
+                .filter(|(_, value)| value.is_none())
+                .map(|(i, _)| {
+                    FieldError::new(format!("debtors[{i}].value"), FieldErrorCode::MissingValue)
+                        .with_param("split_mode", mode.as_str())
+                })
+                .collect::<Vec<FieldError>>();
+            if !missing.is_empty() {
+                return Err(missing);
+            }
+            values.iter().flatten().cloned().collect()
+        }
+    };
+    if values.is_empty() {
+        return Err(vec![FieldError::new("debtors", FieldErrorCode::NoDebtor)]);
+    }
+    if mode == SplitMode::Percentage {
+        let total = weights
+            .iter()
+            .fold(BigDecimal::zero(), |acc, weight| acc + weight);
+        if !(&total - BigDecimal::from(100)).is_zero() {
+            return Err(vec![FieldError::new(
+                "debtors",
+                FieldErrorCode::PercentageSum,
+            )
+            .with_param("expected", 100)
+            .with_param("actual", total)]);
+        }
+    }
+
+    distribute(amount, &weights, scale).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn values(values: &[&str]) -> Vec<Option<BigDecimal>> {
+        values.iter().map(|value| Some(decimal(value))).collect()
+    }
+
+    fn strings(parts: Option<Vec<BigDecimal>>) -> Vec<String> {
+        parts
+            .unwrap_or_default()
+            .iter()
+            .map(|part| part.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn exact_split_is_left_untouched() {
+        let parts = compute_split(SplitMode::Exact, &decimal("10"), &[None, None], 2).unwrap();
+        assert!(parts.is_none());
+    }
+
+    #[test]
+    fn equal_split_distributes_remainder() {
+        let parts = compute_split(SplitMode::Equal, &decimal("10"), &[None, None, None], 2);
+        assert_eq!(strings(parts.unwrap()), vec!["3.34", "3.33", "3.33"]);
+
+        let parts = compute_split(SplitMode::Equal, &decimal("100"), &[None, None, None], 0);
+        assert_eq!(strings(parts.unwrap()), vec!["34", "33", "33"]);
+    }
+
+    #[test]
+    fn equal_split_uses_default_weights() {
+        let values = [Some(decimal("2")), None, Some(decimal("0.5"))];
+        let parts = compute_split(SplitMode::Equal, &decimal("35"), &values, 2);
+        assert_eq!(strings(parts.unwrap()), vec!["20.00", "10.00", "5.00"]);
+    }
+
+    #[test]
+    fn shares_split() {
+        let parts = compute_split(
+            SplitMode::Shares,
+            &decimal("100"),
+            &values(&["2", "1", "1"]),
+            2,
+        );
+        assert_eq!(strings(parts.unwrap()), vec!["50.00", "25.00", "25.00"]);
+
+        let parts = compute_split(
+            SplitMode::Shares,
+            &decimal("10"),
+            &values(&["1", "1", "0.5"]),
+            2,
+        );
+        assert_eq!(strings(parts.unwrap()), vec!["4.00", "4.00", "2.00"]);
+
+        assert!(compute_split(SplitMode::Shares, &decimal("10"), &values(&["0", "0"]), 2).is_err());
+        assert!(
+            compute_split(SplitMode::Shares, &decimal("10"), &values(&["-1", "2"]), 2).is_err()
+        );
+        assert!(compute_split(SplitMode::Shares, &decimal("10"), &[None], 2).is_err());
+    }
+
+    #[test]
+    fn percentage_split() {
+        let parts = compute_split(
+            SplitMode::Percentage,
+            &decimal("20.01"),
+            &values(&["50", "50"]),
+            2,
+        );
+        assert_eq!(strings(parts.unwrap()), vec!["10.01", "10.00"]);
+
+        assert!(compute_split(
+            SplitMode::Percentage,
+            &decimal("10"),
+            &values(&["50", "40"]),
+            2
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parts_sum_to_amount() {
+        let amount = decimal("100.005");
+        let parts = distribute(
+            &amount,
+            &values(&["1", "1", "1"])
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>(),
+            2,
+        )
+        .unwrap();
+        let total = parts
+            .iter()
+            .fold(BigDecimal::zero(), |acc, part| acc + part);
+        assert_eq!(total, amount);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::auth::AuthUser;
+use crate::entrypoint::events::GroupEventKind;
+use crate::entrypoint::group_members::{
+    add_group_member, delete_group_member, find_group_member, get_member_weights, GroupMember,
+};
+use crate::entrypoint::groups::{
+    find_group, find_writable_group_id, get_group_id, upsert_group, GroupNoID,
+};
+use crate::entrypoint::roles::{get_role, Role};
+use crate::entrypoint::tombstones::TombstoneEntity;
+use crate::entrypoint::transactions::{
+    delete_transaction, find_transaction, get_stored_kind, modify_create_transaction,
+    TransactionDelete, TransactionOptions, TransactionQuery, TransactionResponse,
+};
+use crate::entrypoint::validation::{FieldError, ValidationErrors};
+use crate::entrypoint::{ApiError, AppError};
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use diesel::result::DatabaseErrorKind;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    /// The change was saved.
+    Applied,
+    /// The server already holds a more recent version, the change was dropped.
+    Stale,
+    /// The change contradicts the server state (nickname taken, member with debts...).
+    Conflict,
+    /// The change cannot be applied (unknown member, wrong amounts...).
+    Invalid,
+}
+
+/// Every change made offline by a client, applied in a single database
+/// transaction.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncPayload {
+    #[serde(default)]
+    pub group: Option<GroupNoID>,
+    #[serde(default)]
+    pub group_members: Vec<GroupMember>,
+    #[serde(default)]
+    pub deleted_group_members: Vec<GroupMember>,
+    #[serde(default)]
+    pub transactions: Vec<TransactionQuery>,
+    #[serde(default)]
+    pub deleted_transactions: Vec<TransactionDelete>,
+    /// Create the payers and debtors of the transactions missing from the
+    /// group, for groups made offline whose members were not listed
+    #[serde(default)]
+    pub create_members: bool,
+}
+
+/// Outcome of the change of one item
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ItemResult<T> {
+    /// Uuid of the item, the token for the group
+    pub uuid: String,
+    pub status: SyncStatus,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Field-level problems of an invalid item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// Version of the item held by the server once the change is processed,
+    /// for the client to reconcile a rejected change. None if it does not exist.
+    pub current: Option<T>,
+}
+
+impl SyncResponse {
+    /// Every applied item, with the kind of change it made
+    fn applied(&self) -> Vec<(GroupEventKind, TombstoneEntity, &str)> {
+        use GroupEventKind::{Deleted, Updated};
+        let mut applied = vec![];
+        for uuid in self.group.iter().filter_map(ItemResult::applied) {
+            applied.push((Updated, TombstoneEntity::Group, uuid));
+        }
+        for uuid in self.group_members.iter().filter_map(ItemResult::applied) {
+            applied.push((Updated, TombstoneEntity::GroupMember, uuid));
+        }
+        for uuid in self.transactions.iter().filter_map(ItemResult::applied) {
+            applied.push((Updated, TombstoneEntity::Transaction, uuid));
+        }
+        for uuid in self
+            .deleted_transactions
+            .iter()
+            .filter_map(ItemResult::applied)
+        {
+            applied.push((Deleted, TombstoneEntity::Transaction, uuid));
+        }
+        for uuid in self
+            .deleted_group_members
+            .iter()
+            .filter_map(ItemResult::applied)
+        {
+            applied.push((Deleted, TombstoneEntity::GroupMember, uuid));
+        }
+        applied
+    }
+}
+
+impl<T> ItemResult<T> {
+    pub fn new(uuid: &str, status: SyncStatus, current: Option<T>) -> Self {
+        Self {
+            uuid: uuid.to_string(),
+            status,
+            message: None,
+            errors: vec![],
+            current,
+        }
+    }
+
+    /// Uuid of the item if the change was applied
+    fn applied(&self) -> Option<&str> {
+        (self.status == SyncStatus::Applied).then_some(self.uuid.as_str())
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    pub fn with_errors(mut self, errors: ValidationErrors) -> Self {
+        self.message = Some(errors.to_string());
+        self.errors = errors.0;
+        self
+    }
+}
+
+/// One result per item of the payload, in the same order.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncResponse {
+    pub group: Option<ItemResult<GroupNoID>>,
+    pub group_members: Vec<ItemResult<GroupMember>>,
+    pub deleted_group_members: Vec<ItemResult<GroupMember>>,
+    pub transactions: Vec<ItemResult<TransactionResponse>>,
+    pub deleted_transactions: Vec<ItemResult<TransactionResponse>>,
+}
+
+/// Apply one item in a savepoint: a rejected item is rolled back alone and
+/// reported, any other error aborts the whole batch. `current` loads the
+/// server version of the item afterwards.
+fn apply_item<T, F, C>(
+    uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+    f: F,
+    current: C,
+) -> Result<ItemResult<T>, anyhow::Error>
+where
+    F: FnOnce(
+        &mut PooledConnection<ConnectionManager<PgConnection>>,
+    ) -> Result<SyncStatus, anyhow::Error>,
+    C: FnOnce(
+        &mut PooledConnection<ConnectionManager<PgConnection>>,
+    ) -> Result<Option<T>, anyhow::Error>,
+{
+    let error = match conn.transaction::<SyncStatus, anyhow::Error, _>(f) {
+        Ok(status) => return Ok(ItemResult::new(uuid, status, current(conn)?)),
+        Err(error) => error,
+    };
+    if let Some(errors) = error.downcast_ref::<ValidationErrors>() {
+        return Ok(
+            ItemResult::new(uuid, SyncStatus::Invalid, current(conn)?).with_errors(errors.clone())
+        );
+    }
+    if error.downcast_ref::<ApiError>() == Some(&ApiError::LastOwner) {
+        return Ok(ItemResult::new(uuid, SyncStatus::Conflict, current(conn)?)
+            .with_message(error.to_string()));
+    }
+    let status = match error.downcast_ref::<diesel::result::Error>() {
+        Some(diesel::result::Error::NotFound) => SyncStatus::Invalid,
+        Some(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            SyncStatus::Conflict
+        }
+        Some(diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::ForeignKeyViolation | DatabaseErrorKind::CheckViolation,
+            _,
+        )) => SyncStatus::Invalid,
+        _ => return Err(error),
+    };
+    Ok(ItemResult::new(uuid, status, current(conn)?).with_message(error.to_string()))
+}
+
+/// Apply the payload in dependency order: the group, the members, the
+/// transactions using them, then the deletions in reverse order.
+/// The caller needs to be an editor, or the owner if the payload deletes anything.
+fn sync(
+    token: &str,
+    payload: SyncPayload,
+    user: Option<AuthUser>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<SyncResponse, anyhow::Error> {
+    let mut response = SyncResponse::default();
+
+    // Only a group that does not exist yet skips the role check, a share link
+    // or a deleted group is refused
+    if let Some(group_id) = find_writable_group_id(token, conn)? {
+        let required = if payload.deleted_group_members.is_empty()
+            && payload.deleted_transactions.is_empty()
+        {
+            Role::Editor
+        } else {
+            Role::Owner
+        };
+        if get_role(group_id, user, conn)? < required {
+            return Err(ApiError::Forbidden.into());
+        }
+    }
+
+    if let Some(group) = payload.group {
+        response.group = Some(if group.token != token {
+            ItemResult::new(&group.token, SyncStatus::Invalid, None)
+                .with_message("The group token does not match the url".to_string())
+        } else {
+            apply_item(
+                &group.token,
+                conn,
+                |conn| upsert_group(&group, conn),
+                |conn| find_group(&group.token, conn),
+            )?
+        });
+    }
+    let group_id = get_group_id(token, conn)?;
+    let options = TransactionOptions {
+        create_members: payload.create_members,
+    };
+
+    for member in payload.group_members {
+        let result = apply_item(
+            &member.uuid,
+            conn,
+            |conn| add_group_member(group_id, member.clone(), conn),
+            |conn| find_group_member(group_id, &member, conn),
+        )?;
+        response.group_members.push(result);
+    }
+
+    // After the members, whose weights may have just changed
+    let weights = get_member_weights(group_id, conn)?;
+    for mut transaction in payload.transactions {
+        let uuid = transaction.get_uuid();
+        let stored_kind = get_stored_kind(group_id, &uuid, conn)?;
+        let result = match transaction.prepare(&weights, stored_kind) {
+            Err(errors) => ItemResult::new(
+                &uuid,
+                SyncStatus::Invalid,
+                find_transaction(group_id, &uuid, conn)?,
+            )
+            .with_errors(errors),
+            Ok(()) => apply_item(
+                &uuid,
+                conn,
+                |conn| modify_create_transaction(token.to_string(), transaction, options, conn),
+                |conn| find_transaction(group_id, &uuid, conn),
+            )?,
+        };
+        response.transactions.push(result);
+    }
+
+    for transaction in payload.deleted_transactions {
+        let result = apply_item(
+            &transaction.uuid,
+            conn,
+            |conn| delete_transaction(group_id, &transaction, conn),
+            |conn| find_transaction(group_id, &transaction.uuid, conn),
+        )?;
+        response.deleted_transactions.push(result);
+    }
+
+    for member in payload.deleted_group_members {
+        let result = apply_item(
+            &member.uuid,
+            conn,
+            |conn| delete_group_member(group_id, &member, conn),
+            |conn| find_group_member(group_id, &member, conn),
+        )?;
+        response.deleted_group_members.push(result);
+    }
+
+    Ok(response)
+}
+
+///v2/groups/{token_id}/sync
+pub async fn handler_sync(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+    Json(payload): Json<SyncPayload>,
+) -> Result<Json<SyncResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let response = conn
+        .transaction::<SyncResponse, anyhow::Error, _>(|conn| sync(&token, payload, user, conn))
+        .map_err(AppError::from)?;
+    for (kind, entity, uuid) in response.applied() {
+        state_server.notify(&token, kind, entity, uuid);
+    }
+
+    Ok(Json(response))
+}
@@ -0,0 +1,145 @@
+use crate::auth::AuthUser;
+use crate::entrypoint::events::GroupEventKind;
+use crate::entrypoint::groups::{get_group, get_group_id, GroupNoID};
+use crate::entrypoint::roles::{require_role, Role};
+use crate::entrypoint::tombstones::TombstoneEntity;
+use crate::entrypoint::{ApiError, AppError};
+use crate::schema::{groups, token_aliases};
+pub use crate::state_server;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use serde::{Deserialize, Serialize};
+
+/// How long the previous token of a group keeps working after a rotation,
+/// for offline clients to come back and learn the new one
+const TOKEN_GRACE_DAYS: i64 = 7;
+
+/// Group id of the alias `token`, GONE once its grace window is over.
+/// None if `token` never was the token of a group.
+pub fn find_token_alias(
+    token: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<i32>, anyhow::Error> {
+    let alias = token_aliases::table
+        .select((token_aliases::group_id, token_aliases::expires_at))
+        .filter(token_aliases::token.eq(token))
+        .get_result::<(i32, NaiveDateTime)>(conn)
+        .optional()?;
+
+    match alias {
+        Some((_, expires_at)) if expires_at <= chrono::Utc::now().naive_utc() => {
+            Err(ApiError::Gone.into())
+        }
+        Some((group_id, _)) => Ok(Some(group_id)),
+        None => Ok(None),
+    }
+}
+
+/// Current token of the group `token` refers to, `token` itself unless it is
+/// a previous token of the group
+pub fn current_token(
+    token: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<String, anyhow::Error> {
+    match find_token_alias(token, conn)? {
+        Some(group_id) => Ok(groups::table
+            .select(groups::token)
+            .filter(groups::id.eq(group_id))
+            .get_result::<String>(conn)?),
+        None => Ok(token.to_string()),
+    }
+}
+
+/// Every token of the group still giving access to it, the current one first
+pub fn get_group_tokens(
+    group_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut tokens = vec![get_group(group_id, conn)?.token];
+    tokens.extend(
+        token_aliases::table
+            .select(token_aliases::token)
+            .filter(token_aliases::group_id.eq(group_id))
+            .filter(token_aliases::expires_at.gt(chrono::Utc::now().naive_utc()))
+            .load::<String>(conn)?,
+    );
+    Ok(tokens)
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct RotateTokenQuery {
+    /// Invalidate the previous tokens at once instead of after the grace window
+    #[serde(default)]
+    pub revoke: bool,
+}
+
+///groups/{token_id}/token
+/// Issue a new token for the group. Only the current token can rotate it, not
+/// a previous one still in its grace window. Clients listening to the events
+/// of the group get an `updated` group event and reconnect with the new token.
+pub async fn handler_rotate_token(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+    user: Option<AuthUser>,
+    Json(query): Json<RotateTokenQuery>,
+) -> Result<Json<GroupNoID>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let group = conn
+        .transaction::<GroupNoID, anyhow::Error, _>(|conn| {
+            let group_id = get_group_id(&token, conn)?;
+            require_role(group_id, user, Role::Owner, conn)?;
+            if get_group(group_id, conn)?.token != token {
+                return Err(ApiError::Forbidden.into());
+            }
+
+            let now = chrono::Utc::now().naive_utc();
+            let expires_at = if query.revoke {
+                // Previous aliases leaked as well
+                diesel::update(token_aliases::table)
+                    .filter(token_aliases::group_id.eq(group_id))
+                    .filter(token_aliases::expires_at.gt(now))
+                    .set(token_aliases::expires_at.eq(now))
+                    .execute(conn)?;
+                now
+            } else {
+                now + chrono::Duration::days(TOKEN_GRACE_DAYS)
+            };
+            diesel::insert_into(token_aliases::table)
+                .values((
+                    token_aliases::group_id.eq(group_id),
+                    token_aliases::token.eq(&token),
+                    token_aliases::expires_at.eq(expires_at),
+                ))
+                .execute(conn)?;
+
+            Ok(diesel::update(groups::table)
+                .filter(groups::id.eq(group_id))
+                .set((
+                    groups::token.eq(uuid::Uuid::new_v4().to_string()),
+                    groups::modified_at.eq(now),
+                ))
+                .returning(GroupNoID::as_returning())
+                .get_result::<GroupNoID>(conn)?)
+        })
+        .map_err(AppError::from)?;
+    let mut listeners = vec![group.token.clone()];
+    if !query.revoke {
+        listeners.push(token);
+    }
+    for listener in listeners {
+        state_server.notify(
+            &listener,
+            GroupEventKind::Updated,
+            TombstoneEntity::Group,
+            &listener,
+        );
+    }
+
+    Ok(Json(group))
+}
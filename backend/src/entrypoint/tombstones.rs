@@ -0,0 +1,188 @@
+use crate::schema::tombstones;
+use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::PooledConnection;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::upsert::excluded;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum TombstoneEntity {
+    Group,
+    GroupMember,
+    Transaction,
+}
+
+impl TombstoneEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TombstoneEntity::Group => "group",
+            TombstoneEntity::GroupMember => "group_member",
+            TombstoneEntity::Transaction => "transaction",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for TombstoneEntity {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for TombstoneEntity {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "group" => Ok(TombstoneEntity::Group),
+            "group_member" => Ok(TombstoneEntity::GroupMember),
+            "transaction" => Ok(TombstoneEntity::Transaction),
+            other => Err(format!("Unknown tombstone entity {other}").into()),
+        }
+    }
+}
+
+/// A row deleted on the server. The uuid of a group is its token.
+#[derive(Queryable, Selectable, Debug, Serialize, Deserialize, Clone)]
+#[diesel(table_name = crate::schema::tombstones)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Tombstone {
+    pub entity: TombstoneEntity,
+    pub uuid: String,
+    pub modified_at: NaiveDateTime,
+    /// Uuid of the row the deleted one was merged into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+}
+
+/// Record the deletion of a row, keeping the most recent deletion date
+pub fn add_tombstone(
+    group_id: i32,
+    entity: TombstoneEntity,
+    uuid: &str,
+    modified_at: NaiveDateTime,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    insert_tombstone(group_id, entity, uuid, None, modified_at, conn)
+}
+
+/// Record that a row was merged into the row `redirect`
+pub fn add_redirect(
+    group_id: i32,
+    entity: TombstoneEntity,
+    uuid: &str,
+    redirect: &str,
+    modified_at: NaiveDateTime,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    insert_tombstone(group_id, entity, uuid, Some(redirect), modified_at, conn)
+}
+
+fn insert_tombstone(
+    group_id: i32,
+    entity: TombstoneEntity,
+    uuid: &str,
+    redirect: Option<&str>,
+    modified_at: NaiveDateTime,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<(), anyhow::Error> {
+    use diesel::query_dsl::methods::FilterDsl;
+    diesel::insert_into(tombstones::table)
+        .values((
+            tombstones::group_id.eq(group_id),
+            tombstones::entity.eq(entity),
+            tombstones::uuid.eq(uuid),
+            tombstones::modified_at.eq(modified_at),
+            tombstones::redirect.eq(redirect),
+        ))
+        .on_conflict((tombstones::entity, tombstones::uuid))
+        .do_update()
+        .set((
+            tombstones::group_id.eq(excluded(tombstones::group_id)),
+            tombstones::modified_at.eq(excluded(tombstones::modified_at)),
+            tombstones::redirect.eq(excluded(tombstones::redirect)),
+        ))
+        .filter(tombstones::modified_at.lt(excluded(tombstones::modified_at)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Uuid of the row `uuid` was merged into, following successive merges
+pub fn follow_redirect(
+    entity: TombstoneEntity,
+    uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<String>, anyhow::Error> {
+    let mut redirect = None;
+    let mut current = uuid.to_string();
+    let mut seen = HashSet::from([current.clone()]);
+    while let Some(next) =
+        get_tombstone(entity, &current, conn)?.and_then(|tombstone| tombstone.redirect)
+    {
+        if !seen.insert(next.clone()) {
+            break;
+        }
+        redirect = Some(next.clone());
+        current = next;
+    }
+    Ok(redirect)
+}
+
+pub fn get_tombstone(
+    entity: TombstoneEntity,
+    uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<Tombstone>, anyhow::Error> {
+    let tombstone = tombstones::table
+        .select(Tombstone::as_select())
+        .filter(tombstones::entity.eq(entity))
+        .filter(tombstones::uuid.eq(uuid))
+        .first::<Tombstone>(conn)
+        .optional()?;
+    Ok(tombstone)
+}
+
+/// Deletions of a group rows, only the ones written on the server after `since` if given
+pub fn get_tombstones(
+    group_id: i32,
+    since: Option<NaiveDateTime>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<Tombstone>, anyhow::Error> {
+    let mut query = tombstones::table
+        .select(Tombstone::as_select())
+        .filter(tombstones::group_id.eq(group_id))
+        .into_boxed();
+    if let Some(since) = since {
+        query = query.filter(tombstones::updated_at.gt(since));
+    }
+    Ok(query.load::<Tombstone>(conn)?)
+}
+
+/// Last write wins against deletions: an upsert older than the deletion of
+/// its row is dropped, a newer one brings the row back to life. A merged row
+/// never comes back.
+pub fn is_deleted(
+    entity: TombstoneEntity,
+    uuid: &str,
+    modified_at: NaiveDateTime,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<bool, anyhow::Error> {
+    match get_tombstone(entity, uuid, conn)? {
+        Some(tombstone) if tombstone.redirect.is_some() => Ok(true),
+        Some(tombstone) if tombstone.modified_at >= modified_at => Ok(true),
+        Some(_) => {
+            diesel::delete(tombstones::table)
+                .filter(tombstones::entity.eq(entity))
+                .filter(tombstones::uuid.eq(uuid))
+                .execute(conn)?;
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
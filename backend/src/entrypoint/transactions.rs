@@ -1,14 +1,25 @@
-use crate::entrypoint::group_members::get_member_id;
+use crate::auth::AuthUser;
+use crate::entrypoint::currencies::{is_known_currency, minor_unit};
+use crate::entrypoint::events::GroupEventKind;
 use crate::entrypoint::group_members::GroupMemberNoDate;
-use crate::entrypoint::AppError;
+use crate::entrypoint::group_members::{
+    add_group_member, get_member_id, get_member_weights, GroupMember,
+};
+use crate::entrypoint::roles::{require_role, Role};
+use crate::entrypoint::share_links::get_readable_group_id;
+use crate::entrypoint::splits::{compute_split, SplitMode};
+use crate::entrypoint::sync::{ItemResult, SyncStatus};
+use crate::entrypoint::tombstones::{
+    add_tombstone, follow_redirect, get_tombstone, is_deleted, TombstoneEntity,
+};
+use crate::entrypoint::validation::{FieldError, FieldErrorCode, ValidationErrors};
+use crate::entrypoint::{ApiError, AppError};
 use crate::schema::group_members;
-use crate::schema::groups;
 use crate::schema::transaction_debts;
 use crate::schema::transactions;
 pub use crate::state_server;
-use axum::http::StatusCode;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::Json,
 };
 use bigdecimal::BigDecimal;
@@ -16,24 +27,78 @@ use bigdecimal::BigDecimal;
 use bigdecimal::One;
 use bigdecimal::Zero;
 use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
 use diesel::upsert::excluded;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 const MAX_DESCRIPTION_SIZE: usize = 250;
 
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    /// Money spent for the group, shared between the debtors.
+    #[default]
+    Expense,
+    /// Money given back from one member to another to settle a debt.
+    Transfer,
+    /// Money received by the group (refund, income), shared between the debtors.
+    #[serde(alias = "refund")]
+    Income,
+}
+
+impl TransactionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionKind::Expense => "expense",
+            TransactionKind::Transfer => "transfer",
+            TransactionKind::Income => "income",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for TransactionKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<Text, Pg> for TransactionKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "expense" => Ok(TransactionKind::Expense),
+            "transfer" => Ok(TransactionKind::Transfer),
+            "income" => Ok(TransactionKind::Income),
+            other => Err(format!("Unknown transaction kind {other}").into()),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Queryable, Debug, Clone)]
 pub struct TransactionDebtQuery {
     id: Option<i32>,
+    #[serde(default)]
     amount: BigDecimal,
+    /// Weight or percentage of the debtor, depending on the split mode
+    #[serde(default)]
+    value: Option<BigDecimal>,
     member: GroupMemberNoDate,
 }
 
 #[derive(Deserialize, Serialize, Queryable, Debug)]
 pub struct TransactionDebtResponse {
-    id: i32,
-    amount: BigDecimal,
-    member: GroupMemberNoDate,
+    pub id: i32,
+    pub amount: BigDecimal,
+    #[serde(default)]
+    pub value: Option<BigDecimal>,
+    pub member: GroupMemberNoDate,
 }
 
 #[derive(Deserialize, Serialize, Queryable, Debug)]
@@ -46,17 +111,22 @@ pub struct TransactionResponse {
     pub modified_at: NaiveDateTime,
     pub amount: BigDecimal,
     pub exchange_rate: BigDecimal,
+    #[serde(default)]
+    pub kind: TransactionKind,
+    #[serde(default)]
+    pub split_mode: SplitMode,
     pub debtors: Vec<TransactionDebtResponse>,
 }
 
-pub async fn handler_get_all_transactions(
-    State(state_server): State<state_server::StateServer>,
-    Path(token): Path<String>,
-) -> Result<Json<Vec<TransactionResponse>>, AppError> {
-    let mut conn = state_server.pool.get()?;
-
-    let transaction_result = groups::table
-        .inner_join(transactions::table)
+/// Load the transactions of a group with their debtors, optionally only one
+/// transaction or the ones written on the server after `since`.
+fn load_transactions(
+    group_id: i32,
+    transaction_uuid: Option<&str>,
+    since: Option<NaiveDateTime>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<TransactionResponse>, anyhow::Error> {
+    let mut query = transactions::table
         .inner_join(group_members::table.on(group_members::id.eq(transactions::paid_by)))
         .select((
             transactions::id,
@@ -67,36 +137,50 @@ pub async fn handler_get_all_transactions(
             transactions::exchange_rate,
             transactions::modified_at,
             transactions::currency_id,
+            transactions::kind,
+            transactions::split_mode,
             group_members::nickname,
             group_members::uuid,
         ))
-        .filter(groups::token.eq(&token))
-        .load::<(
-            i32,
-            String,
-            String,
-            NaiveDateTime,
-            BigDecimal,
-            BigDecimal,
-            NaiveDateTime,
-            String,
-            String,
-            String,
-        )>(&mut conn)?;
+        .filter(transactions::group_id.eq(group_id))
+        .into_boxed();
+    if let Some(transaction_uuid) = transaction_uuid {
+        query = query.filter(transactions::uuid.eq(transaction_uuid));
+    }
+    if let Some(since) = since {
+        query = query.filter(transactions::updated_at.gt(since));
+    }
+    let transaction_result = query.load::<(
+        i32,
+        String,
+        String,
+        NaiveDateTime,
+        BigDecimal,
+        BigDecimal,
+        NaiveDateTime,
+        String,
+        TransactionKind,
+        SplitMode,
+        String,
+        String,
+    )>(conn)?;
 
+    let transaction_ids = transaction_result
+        .iter()
+        .map(|transaction| transaction.0)
+        .collect::<Vec<i32>>();
     let debts = transaction_debts::table
-        .inner_join(transactions::table)
         .inner_join(group_members::table)
-        .inner_join(groups::table.on(transactions::group_id.eq(groups::id)))
-        .filter(groups::token.eq(token))
+        .filter(transaction_debts::transaction_id.eq_any(&transaction_ids))
         .select((
             transaction_debts::id,
             transaction_debts::transaction_id,
             transaction_debts::amount,
+            transaction_debts::split_value,
             group_members::nickname,
             group_members::uuid,
         ))
-        .load::<(i32, i32, BigDecimal, String, String)>(&mut conn)?;
+        .load::<(i32, i32, BigDecimal, Option<BigDecimal>, String, String)>(conn)?;
 
     let mut map: HashMap<i32, TransactionResponse> = HashMap::new();
     transaction_result.into_iter().for_each(
@@ -109,6 +193,8 @@ pub async fn handler_get_all_transactions(
             exchange_rate,
             modified_at,
             currency_id,
+            kind,
+            split_mode,
             nickname,
             member_uuid,
         )| {
@@ -126,30 +212,70 @@ pub async fn handler_get_all_transactions(
                     currency_id,
                     amount,
                     exchange_rate,
+                    kind,
+                    split_mode,
                     debtors: Vec::new(),
                 },
             );
         },
     );
 
-    debts
-        .into_iter()
-        .for_each(|(debt_id, transaction_id, amount, nickname, member_uuid)| {
+    debts.into_iter().for_each(
+        |(debt_id, transaction_id, amount, split_value, nickname, member_uuid)| {
             if let Some(value) = map.get_mut(&transaction_id) {
                 value.debtors.push(TransactionDebtResponse {
                     id: debt_id,
                     amount,
+                    value: split_value,
                     member: GroupMemberNoDate {
                         uuid: member_uuid,
                         nickname,
                     },
                 });
             }
-        });
+        },
+    );
 
     let mut v = map.into_values().collect::<Vec<TransactionResponse>>();
-    v.sort_by(|a: &TransactionResponse, b: &TransactionResponse| a.created_at.cmp(&b.created_at));
-    Ok(Json(v))
+    v.sort_by_key(|a: &TransactionResponse| a.created_at);
+    Ok(v)
+}
+
+/// Transactions of a group, only the ones written on the server after `since` if given
+pub fn get_transactions(
+    group_id: i32,
+    since: Option<NaiveDateTime>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<TransactionResponse>, anyhow::Error> {
+    load_transactions(group_id, None, since, conn)
+}
+
+/// Transaction `transaction_uuid` of a group if it exists
+pub fn find_transaction(
+    group_id: i32,
+    transaction_uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<TransactionResponse>, anyhow::Error> {
+    Ok(load_transactions(group_id, Some(transaction_uuid), None, conn)?.pop())
+}
+
+pub fn get_transaction(
+    group_id: i32,
+    transaction_uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<TransactionResponse, anyhow::Error> {
+    find_transaction(group_id, transaction_uuid, conn)?.ok_or(diesel::NotFound.into())
+}
+
+pub async fn handler_get_all_transactions(
+    State(state_server): State<state_server::StateServer>,
+    Path(token): Path<String>,
+) -> Result<Json<Vec<TransactionResponse>>, AppError> {
+    let mut conn = state_server.pool.get()?;
+
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let transactions = get_transactions(group_id, None, &mut conn)?;
+    Ok(Json(transactions))
 }
 
 pub async fn handler_get_transaction(
@@ -158,86 +284,9 @@ pub async fn handler_get_transaction(
 ) -> Result<Json<TransactionResponse>, AppError> {
     let mut conn = state_server.pool.get()?;
 
-    let (
-        uuid,
-        description,
-        created_at,
-        amount,
-        exchange_rate,
-        modified_at,
-        currency_id,
-        nickname,
-        member_uuid,
-    ) = groups::table
-        .inner_join(transactions::table)
-        .inner_join(group_members::table)
-        .select((
-            transactions::uuid,
-            transactions::description,
-            transactions::created_at,
-            transactions::amount,
-            transactions::exchange_rate,
-            transactions::modified_at,
-            transactions::currency_id,
-            group_members::nickname,
-            group_members::uuid,
-        ))
-        .filter(groups::token.eq(&token))
-        .filter(transactions::uuid.eq(&transaction_uuid))
-        .get_result::<(
-            String,
-            String,
-            NaiveDateTime,
-            BigDecimal,
-            BigDecimal,
-            NaiveDateTime,
-            String,
-            String,
-            String,
-        )>(&mut conn)?;
-    let mut transaction_response = TransactionResponse {
-        amount,
-        created_at,
-        currency_id,
-        description,
-        debtors: Vec::new(),
-        exchange_rate,
-        uuid,
-        modified_at,
-        paid_by: GroupMemberNoDate {
-            uuid: member_uuid,
-            nickname,
-        },
-    };
-
-    let debts = transaction_debts::table
-        .inner_join(transactions::table)
-        .inner_join(group_members::table)
-        .inner_join(groups::table.on(transactions::group_id.eq(groups::id)))
-        .filter(groups::token.eq(token))
-        .filter(transactions::uuid.eq(&transaction_uuid))
-        .select((
-            transaction_debts::id,
-            transaction_debts::amount,
-            group_members::uuid,
-            group_members::nickname,
-        ))
-        .load::<(i32, BigDecimal, String, String)>(&mut conn)?;
-
-    debts
-        .into_iter()
-        .for_each(|(debt_id, amount, member_uuid, nickname)| {
-            transaction_response.debtors.push(TransactionDebtResponse {
-                id: debt_id,
-                amount,
-                member: GroupMemberNoDate {
-                    uuid: member_uuid,
-                    nickname,
-                },
-            });
-        });
-
-    Ok(Json(transaction_response))
+    let group_id = get_readable_group_id(&token, &mut conn)?;
+    let transaction = get_transaction(group_id, &transaction_uuid, &mut conn)?;
+    Ok(Json(transaction))
 }
 
 #[derive(Debug, AsChangeset, Insertable)]
@@ -249,6 +298,9 @@ pub struct TransactionChangeset {
     pub paid_by: i32,
     pub currency_id: String,
     pub exchange_rate: BigDecimal,
+    /// Left unchanged on updates when None
+    pub kind: Option<TransactionKind>,
+    pub split_mode: SplitMode,
     pub created_at: NaiveDateTime,
     pub modified_at: NaiveDateTime,
     pub group_id: i32,
@@ -273,6 +325,7 @@ pub struct TransactionDebtUpsert {
     transaction_id: i32,
     group_member_id: i32,
     amount: BigDecimal,
+    split_value: Option<BigDecimal>,
 }
 
 #[derive(Deserialize, Serialize, Queryable, Debug, Clone)]
@@ -285,6 +338,14 @@ pub struct TransactionQuery {
     exchange_rate: BigDecimal,
     amount: BigDecimal,
     modified_at: NaiveDateTime,
+    /// Absent from older clients: the stored kind is kept, an expense for a
+    /// new transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<TransactionKind>,
+    /// Absent from older clients, which send the debtor amounts: the split
+    /// is then exact, whatever the stored mode
+    #[serde(default)]
+    split_mode: SplitMode,
     debtors: Vec<TransactionDebtQuery>,
 }
 use {bigdecimal::FromPrimitive, chrono::NaiveDate, std::str::FromStr};
@@ -309,6 +370,8 @@ impl TransactionQuery {
             currency_id: "USD".to_string(),
             modified_at: chrono::Utc::now().naive_utc(),
             exchange_rate: BigDecimal::from_i32(1).unwrap_or(BigDecimal::one()),
+            kind: None,
+            split_mode: SplitMode::Exact,
         }
     }
 
@@ -317,10 +380,85 @@ impl TransactionQuery {
         self.debtors.push(TransactionDebtQuery {
             id: None,
             amount: BigDecimal::from_str(amount).unwrap_or(BigDecimal::zero()),
+            value: None,
+            member: member.clone(),
+        });
+    }
+
+    /// Add a debtor whose amount is computed from `value` by the split mode
+    pub fn add_split_debtor(&mut self, member: &GroupMemberNoDate, value: Option<&str>) {
+        self.modified_at = chrono::Utc::now().naive_utc();
+        self.debtors.push(TransactionDebtQuery {
+            id: None,
+            amount: BigDecimal::zero(),
+            value: value.and_then(|value| BigDecimal::from_str(value).ok()),
             member: member.clone(),
         });
     }
 
+    /// Remove every debtor, for an edit to list the new ones
+    pub fn clear_debtors(&mut self) {
+        self.modified_at = chrono::Utc::now().naive_utc();
+        self.debtors.clear();
+    }
+
+    pub fn set_split_mode(&mut self, split_mode: SplitMode) {
+        self.modified_at = chrono::Utc::now().naive_utc();
+        self.split_mode = split_mode;
+    }
+
+    /// Give the debtors without value of an equal or shares split the default
+    /// weight of their member
+    fn fill_default_weights(&mut self, weights: &HashMap<String, BigDecimal>) {
+        if !matches!(self.split_mode, SplitMode::Equal | SplitMode::Shares) {
+            return;
+        }
+        for debt in self.debtors.iter_mut().filter(|debt| debt.value.is_none()) {
+            debt.value = weights.get(&debt.member.uuid).cloned();
+        }
+    }
+
+    /// Fill the debtor amounts from the split mode and its values
+    fn apply_split(&mut self) -> Result<(), Vec<FieldError>> {
+        let values = self
+            .debtors
+            .iter()
+            .map(|debt| debt.value.clone())
+            .collect::<Vec<_>>();
+        if let Some(amounts) = compute_split(
+            self.split_mode,
+            &self.amount,
+            &values,
+            minor_unit(&self.currency_id),
+        )? {
+            for (debt, amount) in self.debtors.iter_mut().zip(amounts) {
+                debt.amount = amount;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the split, with the default `weights` of the members, and check
+    /// the transaction before saving it, as a `stored_kind` one when it leaves
+    /// its kind out. Membership of the payer and debtors is checked on saving.
+    pub(crate) fn prepare(
+        &mut self,
+        weights: &HashMap<String, BigDecimal>,
+        stored_kind: Option<TransactionKind>,
+    ) -> Result<(), ValidationErrors> {
+        self.fill_default_weights(weights);
+        let mut errors = check_transaction_validity(self, self.kind.or(stored_kind));
+        match self.apply_split() {
+            Ok(()) => errors.extend(check_debts_sum(self)),
+            Err(split_errors) => errors.extend(split_errors),
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+
     pub fn set_description(&mut self, description: &str) {
         self.modified_at = chrono::Utc::now().naive_utc();
         self.description = description.to_string();
@@ -331,6 +469,11 @@ impl TransactionQuery {
         self.amount = BigDecimal::from_str(amount).unwrap_or_default();
     }
 
+    pub fn set_kind(&mut self, kind: TransactionKind) {
+        self.modified_at = chrono::Utc::now().naive_utc();
+        self.kind = Some(kind);
+    }
+
     pub fn set_time(&mut self, time: &NaiveDateTime) {
         self.modified_at = *time;
     }
@@ -339,41 +482,192 @@ impl TransactionQuery {
         self.uuid.clone()
     }
 
+    pub fn get_modified_at(&self) -> NaiveDateTime {
+        self.modified_at
+    }
+
     pub fn get_description(&self) -> String {
         self.description.clone()
     }
 }
 
-fn check_transaction_validity(transaction: &TransactionQuery) -> Result<(), String> {
+/// Problems of the transaction, of the given `kind`, that do not depend on
+/// the split
+fn check_transaction_validity(
+    transaction: &TransactionQuery,
+    kind: Option<TransactionKind>,
+) -> Vec<FieldError> {
+    let mut errors = vec![];
     if transaction.amount.le(&BigDecimal::zero()) {
-        return Err("An amount should be strictly positive".to_string());
+        errors.push(FieldError::new("amount", FieldErrorCode::NotPositive));
+    }
+    if transaction.exchange_rate.le(&BigDecimal::zero()) {
+        errors.push(FieldError::new(
+            "exchange_rate",
+            FieldErrorCode::NotPositive,
+        ));
     }
-    for debt in &transaction.debtors {
+    if !is_known_currency(&transaction.currency_id) {
+        errors.push(
+            FieldError::new("currency_id", FieldErrorCode::UnknownCurrency)
+                .with_param("currency_id", &transaction.currency_id),
+        );
+    }
+    let mut seen = HashMap::new();
+    for (i, debt) in transaction.debtors.iter().enumerate() {
         if debt.amount.lt(&BigDecimal::zero()) {
-            return Err("An amount cannot be negative".to_string());
+            errors.push(FieldError::new(
+                format!("debtors[{i}].amount"),
+                FieldErrorCode::Negative,
+            ));
+        }
+        if let Some(first) = seen.insert(&debt.member.uuid, i) {
+            errors.push(
+                FieldError::new(
+                    format!("debtors[{i}].member"),
+                    FieldErrorCode::DuplicateMember,
+                )
+                .with_param("first", format!("debtors[{first}]")),
+            );
+        }
+    }
+    if kind == Some(TransactionKind::Transfer) {
+        if transaction.debtors.len() != 1 {
+            errors.push(
+                FieldError::new("debtors", FieldErrorCode::TransferDebtorCount)
+                    .with_param("expected", 1)
+                    .with_param("actual", transaction.debtors.len()),
+            );
+        }
+        if transaction
+            .debtors
+            .iter()
+            .any(|debt| debt.member.uuid == transaction.paid_by.uuid)
+        {
+            errors.push(FieldError::new("paid_by", FieldErrorCode::TransferToPayer));
         }
     }
+    errors
+}
+
+fn check_debts_sum(transaction: &TransactionQuery) -> Option<FieldError> {
     let debt_amount = transaction
         .debtors
         .iter()
         .fold(BigDecimal::zero(), |acc, x| acc + &x.amount);
-    if debt_amount.eq(&transaction.amount) {
-        Ok(())
+    (debt_amount != transaction.amount).then(|| {
+        FieldError::new("debtors", FieldErrorCode::SumMismatch)
+            .with_param("expected", &transaction.amount)
+            .with_param("actual", debt_amount)
+    })
+}
+
+/// Kind of the saved transaction `uuid`, None if it does not exist yet
+pub(crate) fn get_stored_kind(
+    group_id: i32,
+    uuid: &str,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Option<TransactionKind>, anyhow::Error> {
+    let kind = transactions::table
+        .select(transactions::kind)
+        .filter(transactions::group_id.eq(group_id))
+        .filter(transactions::uuid.eq(uuid))
+        .get_result::<TransactionKind>(conn)
+        .optional()?;
+    Ok(kind)
+}
+
+/// Ids of the payer and debtors of the transaction, by uuid. Merged members
+/// resolve to the member they were merged into. Members missing from the
+/// group are created if `create_members` is set, otherwise reported as
+/// validation errors.
+fn resolve_transaction_members(
+    group_id: i32,
+    transaction: &TransactionQuery,
+    create_members: bool,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<HashMap<String, i32>, anyhow::Error> {
+    let mut members = group_members::table
+        .select((group_members::uuid, group_members::id))
+        .filter(group_members::group_id.eq(group_id))
+        .load::<(String, i32)>(conn)?
+        .into_iter()
+        .collect::<HashMap<String, i32>>();
+
+    let fields = std::iter::once(("paid_by".to_string(), &transaction.paid_by)).chain(
+        transaction
+            .debtors
+            .iter()
+            .enumerate()
+            .map(|(i, debt)| (format!("debtors[{i}].member"), &debt.member)),
+    );
+    let mut errors = vec![];
+    for (field, member) in fields {
+        if members.contains_key(&member.uuid) {
+            continue;
+        }
+        let redirect = follow_redirect(TombstoneEntity::GroupMember, &member.uuid, conn)?
+            .and_then(|uuid| members.get(&uuid).copied());
+        if let Some(member_id) = redirect {
+            members.insert(member.uuid.clone(), member_id);
+            continue;
+        }
+        if create_members {
+            let new_member = GroupMember {
+                uuid: member.uuid.clone(),
+                nickname: member.nickname.clone(),
+                modified_at: transaction.modified_at,
+                archived: false,
+                weight: None,
+            };
+            if add_group_member(group_id, new_member, conn)? == SyncStatus::Applied {
+                members.insert(
+                    member.uuid.clone(),
+                    get_member_id(group_id, member.uuid.clone(), conn)?,
+                );
+                continue;
+            }
+        }
+        errors.push(
+            FieldError::new(field, FieldErrorCode::UnknownMember).with_param("uuid", &member.uuid),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(members)
     } else {
-        Err(format!(
-            "The amount of debtors {} are not equivalent to the transaction {}",
-            debt_amount, transaction.amount
-        ))
+        Err(ValidationErrors(errors).into())
     }
 }
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy)]
+pub struct TransactionOptions {
+    /// Create the payer and debtors missing from the group, with their uuid
+    /// and nickname, instead of rejecting the transaction
+    #[serde(default)]
+    pub create_members: bool,
+}
+
+/// Insert or update a transaction and its debts, the most recent
+/// `modified_at` wins.
 pub fn modify_create_transaction(
     token_id: String,
     transaction: TransactionQuery,
+    options: TransactionOptions,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-) -> Result<(), anyhow::Error> {
+) -> Result<SyncStatus, anyhow::Error> {
     use unicode_truncate::UnicodeTruncateStr;
     let group_id = get_group_id(&token_id, conn)?;
-    let member_id = get_member_id(group_id, transaction.paid_by.uuid, conn)?;
+    if is_deleted(
+        TombstoneEntity::Transaction,
+        &transaction.uuid,
+        transaction.modified_at,
+        conn,
+    )? {
+        return Ok(SyncStatus::Stale);
+    }
+    let member_ids =
+        resolve_transaction_members(group_id, &transaction, options.create_members, conn)?;
     let changeset = TransactionChangeset {
         uuid: transaction.uuid,
         description: transaction
@@ -383,15 +677,17 @@ pub fn modify_create_transaction(
             .0
             .to_string(),
         amount: transaction.amount,
-        paid_by: member_id,
+        paid_by: member_ids[&transaction.paid_by.uuid],
         currency_id: transaction.currency_id,
         exchange_rate: transaction.exchange_rate,
+        kind: transaction.kind,
+        split_mode: transaction.split_mode,
         created_at: transaction.created_at,
         modified_at: transaction.modified_at,
         group_id,
     };
     use diesel::query_dsl::methods::FilterDsl;
-    if let Ok(transaction_id) = diesel::insert_into(transactions::table)
+    let Some(transaction_id) = diesel::insert_into(transactions::table)
         .values(&changeset)
         .on_conflict(transactions::uuid)
         .do_update()
@@ -399,78 +695,150 @@ pub fn modify_create_transaction(
         .filter(transactions::modified_at.lt(excluded(transactions::modified_at)))
         .returning(transactions::id)
         .get_result::<i32>(conn)
-    {
-        let debts = transaction
-            .debtors
-            .into_iter()
-            .map(|debt| {
-                let id: Option<i32> = if debt.id.is_some_and(|v| v > 0) {
-                    debt.id
-                } else {
-                    None
-                };
-                let member_id = get_member_id(group_id, debt.member.uuid, conn);
-
-                TransactionDebtUpsert {
-                    transaction_id,
-                    group_member_id: member_id.unwrap_or(0),
-                    amount: debt.amount,
-                    // Include ID only for updates
-                    id, //can be optional,
-                }
-            })
-            .collect::<Vec<_>>();
+        .optional()?
+    else {
+        return Ok(SyncStatus::Stale);
+    };
 
-        diesel::insert_into(transaction_debts::table)
-            .values(&debts)
-            .on_conflict((
-                transaction_debts::transaction_id,
-                transaction_debts::group_member_id,
-            ))
-            .do_update()
-            .set(transaction_debts::amount.eq(diesel::upsert::excluded(transaction_debts::amount)))
-            .execute(conn)?;
+    let mut debts: Vec<TransactionDebtUpsert> = vec![];
+    for debt in transaction.debtors {
+        let id: Option<i32> = if debt.id.is_some_and(|v| v > 0) {
+            debt.id
+        } else {
+            None
+        };
+        let group_member_id = member_ids[&debt.member.uuid];
+        // A merged member and the one it was merged into share a debt
+        if let Some(merged) = debts
+            .iter_mut()
+            .find(|current| current.group_member_id == group_member_id)
+        {
+            merged.amount += debt.amount;
+            merged.split_value = None;
+            continue;
+        }
+        debts.push(TransactionDebtUpsert {
+            transaction_id,
+            group_member_id,
+            amount: debt.amount,
+            split_value: debt.value,
+            // Include ID only for updates
+            id, //can be optional,
+        });
     }
 
-    Ok(())
+    // The debtors replace the previous ones: drop the members left out
+    diesel::delete(transaction_debts::table)
+        .filter(transaction_debts::transaction_id.eq(transaction_id))
+        .filter(
+            transaction_debts::group_member_id.ne_all(
+                debts
+                    .iter()
+                    .map(|debt| debt.group_member_id)
+                    .collect::<Vec<i32>>(),
+            ),
+        )
+        .execute(conn)?;
+
+    diesel::insert_into(transaction_debts::table)
+        .values(&debts)
+        .on_conflict((
+            transaction_debts::transaction_id,
+            transaction_debts::group_member_id,
+        ))
+        .do_update()
+        .set((
+            transaction_debts::amount.eq(excluded(transaction_debts::amount)),
+            transaction_debts::split_value.eq(excluded(transaction_debts::split_value)),
+        ))
+        .execute(conn)?;
+
+    Ok(SyncStatus::Applied)
+}
+
+/// Save a transaction and report whether it was applied, along with the
+/// version held by the server
+fn save_transaction(
+    token_id: String,
+    transaction: TransactionQuery,
+    options: TransactionOptions,
+    user: Option<AuthUser>,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<ItemResult<TransactionResponse>, anyhow::Error> {
+    let group_id = get_group_id(&token_id, conn)?;
+    require_role(group_id, user, Role::Editor, conn)?;
+    let uuid = transaction.get_uuid();
+    let status = modify_create_transaction(token_id, transaction, options, conn)?;
+    let current = find_transaction(group_id, &uuid, conn)?;
+    Ok(ItemResult::new(&uuid, status, current))
 }
 
 pub async fn handler_modify_transaction(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
-    Json(payload): Json<TransactionQuery>,
-) -> Result<(), AppError<String>> {
-    check_transaction_validity(&payload).map_err(|v| AppError {
-        content: Some(v),
-        error: anyhow::anyhow!(StatusCode::INTERNAL_SERVER_ERROR),
-    })?;
-
+    Query(options): Query<TransactionOptions>,
+    user: Option<AuthUser>,
+    Json(mut payload): Json<TransactionQuery>,
+) -> Result<Json<ItemResult<TransactionResponse>>, AppError<Vec<FieldError>>> {
     let mut conn = state_server.pool.get()?;
-    conn.transaction::<_, anyhow::Error, _>(|conn| modify_create_transaction(token, payload, conn))
+    let group_id = get_group_id(&token, &mut conn)?;
+    let weights = get_member_weights(group_id, &mut conn)?;
+    let stored_kind = get_stored_kind(group_id, &payload.get_uuid(), &mut conn)?;
+    payload
+        .prepare(&weights, stored_kind)
+        .map_err(|errors| AppError::with_content(ApiError::InvalidTransaction, errors.0))?;
+
+    let result = conn
+        .transaction::<_, anyhow::Error, _>(|conn| {
+            save_transaction(token.clone(), payload, options, user, conn)
+        })
         .map_err(AppError::from)?;
+    if result.status == SyncStatus::Applied {
+        state_server.notify(
+            &token,
+            GroupEventKind::Updated,
+            TombstoneEntity::Transaction,
+            &result.uuid,
+        );
+    }
 
-    Ok(())
+    Ok(Json(result))
 }
 
 pub async fn handler_modify_transactions(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    Query(options): Query<TransactionOptions>,
+    user: Option<AuthUser>,
     Json(transactions): Json<Vec<TransactionQuery>>,
-) -> Result<(), AppError<String>> {
-    for transaction in transactions {
+) -> Result<Json<Vec<ItemResult<TransactionResponse>>>, AppError<Vec<FieldError>>> {
+    let mut results = vec![];
+    for mut transaction in transactions {
         let t = token.clone();
-        check_transaction_validity(&transaction).map_err(|v| AppError {
-            content: Some(v),
-            error: anyhow::anyhow!(StatusCode::INTERNAL_SERVER_ERROR),
-        })?;
         let mut conn = state_server.pool.get()?;
-        conn.transaction::<_, anyhow::Error, _>(|conn| {
-            modify_create_transaction(t, transaction, conn)
-        })
-        .map_err(AppError::from)?;
+        let group_id = get_group_id(&token, &mut conn)?;
+        let weights = get_member_weights(group_id, &mut conn)?;
+        let stored_kind = get_stored_kind(group_id, &transaction.get_uuid(), &mut conn)?;
+        transaction
+            .prepare(&weights, stored_kind)
+            .map_err(|errors| AppError::with_content(ApiError::InvalidTransaction, errors.0))?;
+        let result = conn
+            .transaction::<_, anyhow::Error, _>(|conn| {
+                save_transaction(t, transaction, options, user, conn)
+            })
+            .map_err(AppError::from)?;
+        if result.status == SyncStatus::Applied {
+            state_server.notify(
+                &token,
+                GroupEventKind::Updated,
+                TombstoneEntity::Transaction,
+                &result.uuid,
+            );
+        }
+        results.push(result);
     }
 
-    Ok(())
+    Ok(Json(results))
 }
 
 #[derive(Deserialize, Serialize, Queryable, Debug)]
@@ -481,41 +849,97 @@ pub struct TransactionIDResponse {
 pub async fn handler_delete_transaction(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    user: Option<AuthUser>,
     Json(transaction): Json<TransactionDelete>,
 ) -> Result<(), AppError> {
     let mut conn = state_server.pool.get()?;
-    conn.transaction::<_, anyhow::Error, _>(|conn| {
-        let groud_id = get_group_id(&token, conn)?;
+    let affected = conn
+        .transaction::<_, anyhow::Error, _>(|conn| {
+            let groud_id = get_group_id(&token, conn)?;
+            require_role(groud_id, user, Role::Owner, conn)?;
 
-        diesel::delete(transactions::table)
-            .filter(transactions::group_id.eq(groud_id))
-            .filter(transactions::uuid.eq(transaction.uuid))
-            .filter(transactions::modified_at.lt(transaction.modified_at))
-            .execute(conn)?;
+            let affected = diesel::delete(transactions::table)
+                .filter(transactions::group_id.eq(groud_id))
+                .filter(transactions::uuid.eq(&transaction.uuid))
+                .filter(transactions::modified_at.lt(transaction.modified_at))
+                .execute(conn)?;
+            if affected > 0 {
+                add_tombstone(
+                    groud_id,
+                    TombstoneEntity::Transaction,
+                    &transaction.uuid,
+                    transaction.modified_at,
+                    conn,
+                )?;
+            }
 
-        Ok(())
-    })
-    .map_err(AppError::from)?;
+            Ok(affected)
+        })
+        .map_err(AppError::from)?;
+    if affected > 0 {
+        state_server.notify(
+            &token,
+            GroupEventKind::Deleted,
+            TombstoneEntity::Transaction,
+            &transaction.uuid,
+        );
+    }
 
     Ok(())
 }
 
+/// Delete a transaction not modified after `transaction`, leaving a
+/// tombstone behind. Deleting an already deleted transaction is applied.
+pub fn delete_transaction(
+    group_id: i32,
+    transaction: &TransactionDelete,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<SyncStatus, anyhow::Error> {
+    let affected = diesel::delete(transactions::table)
+        .filter(transactions::group_id.eq(group_id))
+        .filter(transactions::uuid.eq(&transaction.uuid))
+        .filter(transactions::modified_at.le(transaction.modified_at))
+        .execute(conn)?;
+
+    if affected == 0 {
+        // Already deleted by another device
+        if get_tombstone(TombstoneEntity::Transaction, &transaction.uuid, conn)?.is_some() {
+            return Ok(SyncStatus::Applied);
+        }
+        let exists = diesel::select(diesel::dsl::exists(
+            transactions::table
+                .filter(transactions::group_id.eq(group_id))
+                .filter(transactions::uuid.eq(&transaction.uuid)),
+        ))
+        .get_result::<bool>(conn)?;
+        return Ok(if exists {
+            SyncStatus::Stale
+        } else {
+            SyncStatus::Invalid
+        });
+    }
+    add_tombstone(
+        group_id,
+        TombstoneEntity::Transaction,
+        &transaction.uuid,
+        transaction.modified_at,
+        conn,
+    )?;
+    Ok(SyncStatus::Applied)
+}
+
 pub async fn handler_delete_transactions(
     State(state_server): State<state_server::StateServer>,
     Path(token): Path<String>,
+    user: Option<AuthUser>,
     Json(transactions): Json<Vec<TransactionDelete>>,
 ) -> Result<(), AppError> {
     let mut conn = state_server.pool.get()?;
     let groud_id = get_group_id(&token, &mut conn)?;
+    require_role(groud_id, user, Role::Owner, &mut conn)?;
     conn.transaction::<_, anyhow::Error, _>(|conn| {
-        for transaction in transactions {
-            let affected = diesel::delete(transactions::table)
-                .filter(transactions::group_id.eq(groud_id))
-                .filter(transactions::uuid.eq(transaction.uuid))
-                .filter(transactions::modified_at.le(transaction.modified_at))
-                .execute(conn)?;
-
-            if affected == 0 {
+        for transaction in &transactions {
+            if delete_transaction(groud_id, transaction, conn)? != SyncStatus::Applied {
                 // Return Diesel's NotFound which you can convert to 404 via your error handling
                 return Err(diesel::NotFound.into());
             }
@@ -524,50 +948,173 @@ pub async fn handler_delete_transactions(
         Ok(())
     })
     .map_err(AppError::from)?;
+    for transaction in transactions {
+        state_server.notify(
+            &token,
+            GroupEventKind::Deleted,
+            TombstoneEntity::Transaction,
+            &transaction.uuid,
+        );
+    }
 
     Ok(())
 }
 
-#[derive(Deserialize, Serialize, Queryable, Debug, Selectable)]
-#[diesel(table_name = crate::schema::transactions)]
-#[diesel(check_for_backend(diesel::pg::Pg))] // Add backend check
-pub struct TransactionPaidByResponse {
-    pub id: i32,
-    pub description: String,
-    pub currency_id: String,
-    pub created_at: NaiveDateTime,
-    pub amount: BigDecimal,
-}
-
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::PooledConnection;
 
 use super::groups::get_group_id;
-pub fn get_transaction_paid_by(
+
+/// Uuids of the transactions the member paid or owes a non-zero share of
+pub fn get_member_transactions(
     group_id: i32,
     group_member_id: i32,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-) -> Result<Vec<TransactionPaidByResponse>, anyhow::Error> {
-    let transaction_result = transactions::table
-        .inner_join(groups::table)
-        .select(TransactionPaidByResponse::as_select())
-        .filter(groups::id.eq(group_id))
+) -> Result<Vec<String>, anyhow::Error> {
+    let owed = transaction_debts::table
+        .select(transaction_debts::transaction_id)
+        .filter(transaction_debts::group_member_id.eq(group_member_id))
+        .filter(transaction_debts::amount.ne(BigDecimal::zero()));
+    let uuids = transactions::table
+        .select(transactions::uuid)
+        .filter(transactions::group_id.eq(group_id))
+        .filter(
+            transactions::paid_by
+                .eq(group_member_id)
+                .or(transactions::id.eq_any(owed)),
+        )
+        .order(transactions::created_at)
+        .load::<String>(conn)?;
+    Ok(uuids)
+}
+
+/// Transactions changed to let a member be deleted
+#[derive(Debug, Default)]
+pub struct ReleasedTransactions {
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+/// Uuids of the transactions paid by a member
+pub(crate) fn get_paid_transactions(
+    group_id: i32,
+    group_member_id: i32,
+    conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let uuids = transactions::table
+        .select(transactions::uuid)
+        .filter(transactions::group_id.eq(group_id))
         .filter(transactions::paid_by.eq(group_member_id))
-        .load::<TransactionPaidByResponse>(conn)?;
-    Ok(transaction_result)
+        .order(transactions::created_at)
+        .load::<String>(conn)?;
+    Ok(uuids)
 }
 
-pub fn get_transaction_debt(
+/// Move the payments and shares of a member to the member `reassign_to`, or
+/// without it take its shares out of the amount of the transactions, the
+/// payers bearing them. A member who paid a transaction cannot be taken out,
+/// see `get_paid_transactions`. Merged or removed shares turn the split into
+/// an exact one. A transaction left without amount, or a transfer from a
+/// member to itself, is deleted.
+pub(crate) fn release_member_transactions(
     group_id: i32,
     group_member_id: i32,
+    reassign_to: Option<i32>,
     conn: &mut PooledConnection<ConnectionManager<PgConnection>>,
-) -> Result<Vec<(i32, BigDecimal)>, anyhow::Error> {
-    let transaction_result = transaction_debts::table
-        .inner_join(group_members::table)
-        .inner_join(groups::table.on(group_members::group_id.eq(groups::id)))
-        .select((transaction_debts::id, transaction_debts::amount))
+) -> Result<ReleasedTransactions, anyhow::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut touched = BTreeSet::new();
+    let mut to_delete = BTreeSet::new();
+
+    if let Some(target_id) = reassign_to {
+        let paid = diesel::update(transactions::table)
+            .filter(transactions::group_id.eq(group_id))
+            .filter(transactions::paid_by.eq(group_member_id))
+            .set(transactions::paid_by.eq(target_id))
+            .returning(transactions::id)
+            .get_results::<i32>(conn)?;
+        touched.extend(paid);
+    }
+
+    let debts = transaction_debts::table
+        .inner_join(transactions::table)
+        .select((
+            transaction_debts::id,
+            transaction_debts::transaction_id,
+            transaction_debts::amount,
+            transactions::amount,
+        ))
+        .filter(transactions::group_id.eq(group_id))
         .filter(transaction_debts::group_member_id.eq(group_member_id))
-        .filter(groups::id.eq(group_id))
-        .load::<(i32, BigDecimal)>(conn)?;
-    Ok(transaction_result)
+        .load::<(i32, i32, BigDecimal, BigDecimal)>(conn)?;
+    for (debt_id, transaction_id, amount, transaction_amount) in debts {
+        let Some(target_id) = reassign_to else {
+            if amount.is_zero() {
+                continue;
+            }
+            touched.insert(transaction_id);
+            if transaction_amount <= amount {
+                to_delete.insert(transaction_id);
+                continue;
+            }
+            diesel::delete(transaction_debts::table.find(debt_id)).execute(conn)?;
+            diesel::update(transactions::table.find(transaction_id))
+                .set((
+                    transactions::amount.eq(transaction_amount - amount),
+                    transactions::split_mode.eq(SplitMode::Exact),
+                ))
+                .execute(conn)?;
+            continue;
+        };
+
+        touched.insert(transaction_id);
+        let merged = diesel::update(transaction_debts::table)
+            .filter(transaction_debts::transaction_id.eq(transaction_id))
+            .filter(transaction_debts::group_member_id.eq(target_id))
+            .set((
+                transaction_debts::amount.eq(transaction_debts::amount + &amount),
+                transaction_debts::split_value.eq(None::<BigDecimal>),
+            ))
+            .execute(conn)?;
+        if merged > 0 {
+            diesel::delete(transaction_debts::table.find(debt_id)).execute(conn)?;
+            diesel::update(transactions::table.find(transaction_id))
+                .set(transactions::split_mode.eq(SplitMode::Exact))
+                .execute(conn)?;
+        } else {
+            diesel::update(transaction_debts::table.find(debt_id))
+                .set(transaction_debts::group_member_id.eq(target_id))
+                .execute(conn)?;
+        }
+    }
+
+    if let Some(target_id) = reassign_to {
+        let owed_by_target = transaction_debts::table
+            .select(transaction_debts::transaction_id)
+            .filter(transaction_debts::group_member_id.eq(target_id));
+        to_delete.extend(
+            transactions::table
+                .select(transactions::id)
+                .filter(transactions::id.eq_any(&touched))
+                .filter(transactions::kind.eq(TransactionKind::Transfer))
+                .filter(transactions::paid_by.eq(target_id))
+                .filter(transactions::id.eq_any(owed_by_target))
+                .load::<i32>(conn)?,
+        );
+    }
+
+    let deleted = diesel::delete(transactions::table)
+        .filter(transactions::id.eq_any(&to_delete))
+        .returning(transactions::uuid)
+        .get_results::<String>(conn)?;
+    for uuid in &deleted {
+        add_tombstone(group_id, TombstoneEntity::Transaction, uuid, now, conn)?;
+    }
+    let updated = diesel::update(transactions::table)
+        .filter(transactions::id.eq_any(touched.difference(&to_delete).collect::<Vec<_>>()))
+        .set(transactions::modified_at.eq(now))
+        .returning(transactions::uuid)
+        .get_results::<String>(conn)?;
+
+    Ok(ReleasedTransactions { updated, deleted })
 }
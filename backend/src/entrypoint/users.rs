@@ -0,0 +1,137 @@
+use crate::auth::{create_token, hash_password, verify_password, AuthUser};
+use crate::entrypoint::{ApiError, AppError};
+use crate::models::User;
+use crate::schema::users;
+pub use crate::state_server;
+use axum::{extract::State, response::Json};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use serde::{Deserialize, Serialize};
+
+const MIN_PASSWORD_SIZE: usize = 8;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RegisterQuery {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LoginQuery {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct UserResponse {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        UserResponse {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            created_at: user.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TokenResponse {
+    /// To send as `Authorization: Bearer {token}`
+    pub token: String,
+    pub user: UserResponse,
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+///users
+pub async fn handler_register(
+    State(state_server): State<state_server::StateServer>,
+    Json(query): Json<RegisterQuery>,
+) -> Result<Json<TokenResponse>, AppError<String>> {
+    let email = normalize_email(&query.email);
+    if !email.contains('@') {
+        return Err(AppError::with_content(
+            ApiError::InvalidEmail,
+            "Invalid email".to_string(),
+        ));
+    }
+    if query.password.chars().count() < MIN_PASSWORD_SIZE {
+        return Err(AppError::with_content(
+            ApiError::PasswordTooShort,
+            format!("A password needs at least {MIN_PASSWORD_SIZE} characters"),
+        ));
+    }
+    let password_hash = hash_password(query.password).await?;
+
+    let mut conn = state_server.pool.get()?;
+    let user = diesel::insert_into(users::table)
+        .values((
+            users::name.eq(query.name.trim()),
+            users::email.eq(&email),
+            users::password_hash.eq(&password_hash),
+            users::created_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .returning(User::as_returning())
+        .get_result::<User>(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                anyhow::Error::from(ApiError::EmailTaken)
+            }
+            e => e.into(),
+        })?;
+
+    Ok(Json(TokenResponse {
+        token: create_token(user.id)?,
+        user: user.into(),
+    }))
+}
+
+///users/login
+pub async fn handler_login(
+    State(state_server): State<state_server::StateServer>,
+    Json(query): Json<LoginQuery>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let user = users::table
+        .select(User::as_select())
+        .filter(users::email.eq(normalize_email(&query.email)))
+        .get_result::<User>(&mut state_server.pool.get()?)
+        .optional()?;
+    let password_hash = user.as_ref().map(|user| user.password_hash.clone());
+    if !verify_password(query.password, password_hash).await {
+        return Err(ApiError::InvalidCredentials.into());
+    }
+    let user = user.ok_or(ApiError::InvalidCredentials)?;
+
+    Ok(Json(TokenResponse {
+        token: create_token(user.id)?,
+        user: user.into(),
+    }))
+}
+
+///users/me
+pub async fn handler_me(
+    State(state_server): State<state_server::StateServer>,
+    user: AuthUser,
+) -> Result<Json<UserResponse>, AppError> {
+    let mut conn = state_server.pool.get()?;
+    let user = users::table
+        .select(User::as_select())
+        .filter(users::id.eq(user.id))
+        .get_result::<User>(&mut conn)
+        .optional()?
+        // The account was removed since the token was issued
+        .ok_or(ApiError::Unauthorized)?;
+
+    Ok(Json(user.into()))
+}
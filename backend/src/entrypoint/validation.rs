@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldErrorCode {
+    /// The value should be strictly positive.
+    NotPositive,
+    /// The value cannot be negative.
+    Negative,
+    /// The debtor amounts do not sum to the amount of the transaction.
+    SumMismatch,
+    /// A transfer goes to exactly one debtor.
+    TransferDebtorCount,
+    /// A transfer cannot be paid to its payer.
+    TransferToPayer,
+    /// The member is not part of the group.
+    UnknownMember,
+    /// The member is already a debtor of the transaction.
+    DuplicateMember,
+    UnknownCurrency,
+    /// The split mode needs a value for every debtor.
+    MissingValue,
+    /// A split needs at least one debtor.
+    NoDebtor,
+    /// The percentages do not sum to 100.
+    PercentageSum,
+    /// The split values are all zero.
+    ZeroSplit,
+}
+
+impl FieldErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldErrorCode::NotPositive => "not_positive",
+            FieldErrorCode::Negative => "negative",
+            FieldErrorCode::SumMismatch => "sum_mismatch",
+            FieldErrorCode::TransferDebtorCount => "transfer_debtor_count",
+            FieldErrorCode::TransferToPayer => "transfer_to_payer",
+            FieldErrorCode::UnknownMember => "unknown_member",
+            FieldErrorCode::DuplicateMember => "duplicate_member",
+            FieldErrorCode::UnknownCurrency => "unknown_currency",
+            FieldErrorCode::MissingValue => "missing_value",
+            FieldErrorCode::NoDebtor => "no_debtor",
+            FieldErrorCode::PercentageSum => "percentage_sum",
+            FieldErrorCode::ZeroSplit => "zero_split",
+        }
+    }
+}
+
+/// Problem with one field of a request
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    /// Path of the field, such as `debtors[2].amount`
+    pub field: String,
+    pub code: FieldErrorCode,
+    /// Values explaining the problem, such as the expected and actual sums
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: FieldErrorCode) -> Self {
+        Self {
+            field: field.into(),
+            code,
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+/// Every problem found in a transaction, reported at once
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self
+            .0
+            .iter()
+            .map(|error| format!("{} {}", error.field, error.code.as_str()))
+            .collect::<Vec<String>>();
+        write!(f, "Invalid transaction ({})", fields.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
@@ -1,3 +1,4 @@
+pub mod auth;
 pub mod entrypoint;
 pub mod models;
 pub mod router;
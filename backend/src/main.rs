@@ -1,4 +1,5 @@
 use std::{env, str::FromStr};
+pub mod auth;
 pub mod entrypoint;
 pub mod models;
 pub mod schema;
@@ -15,7 +16,7 @@ async fn main() -> anyhow::Result<()> {
     let connection = state_server::establish_connection()?;
     println!("Connection established...");
 
-    let state_server = state_server::StateServer { pool: connection };
+    let state_server = state_server::StateServer::new(connection);
     let front_url = env::var("FRONT_URL")?;
 
     let listening_url = env::var("LISTENING_URL")?;
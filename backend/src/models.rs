@@ -23,6 +23,7 @@ pub struct Group {
     pub token: String,
     pub created_at: NaiveDateTime,
     pub modified_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Associations, Debug, Serialize, Insertable)]
@@ -34,9 +35,14 @@ pub struct GroupMember {
     pub id: i32,
     pub group_id: i32,
     pub nickname: String,
+    pub nickname_key: String,
     pub uuid: String,
     pub modified_at: NaiveDateTime,
     pub user_id: Option<i32>,
+    pub role: String,
+    pub archived: bool,
+    pub weight: Option<BigDecimal>,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(
@@ -54,9 +60,12 @@ pub struct Transaction {
     pub paid_by: i32,
     pub currency_id: String,
     pub exchange_rate: BigDecimal,
+    pub kind: String,
+    pub split_mode: String,
     pub created_at: NaiveDateTime,
     pub uuid: String,
     pub modified_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Associations, Debug, Serialize, Insertable)]
@@ -69,4 +78,18 @@ pub struct TransactionDebt {
     pub transaction_id: i32,
     pub group_member_id: i32,
     pub amount: BigDecimal,
+    pub split_value: Option<BigDecimal>,
+}
+
+#[derive(Queryable, Identifiable, Selectable, Debug, Serialize, Insertable)]
+#[diesel(table_name = crate::schema::tombstones)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Tombstone {
+    pub id: i32,
+    pub group_id: i32,
+    pub entity: String,
+    pub uuid: String,
+    pub modified_at: NaiveDateTime,
+    pub redirect: Option<String>,
+    pub updated_at: NaiveDateTime,
 }
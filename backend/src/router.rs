@@ -1,9 +1,13 @@
-use crate::entrypoint::{group_members, groups, status, transactions};
-use crate::state_server;
+use crate::entrypoint::{
+    balances, changes, events, group_members, groups, invites, roles, settlements, share_links,
+    status, sync, token_aliases, transactions, users,
+};
+use crate::{auth, state_server};
+use axum::middleware;
 use axum::routing::delete;
 use axum::{
     http::HeaderValue,
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 
@@ -24,9 +28,14 @@ pub fn create_router(url: &str, state_server: StateServer) -> Result<Router, any
             axum::http::Method::DELETE,
             axum::http::Method::PATCH,
         ])
-        .allow_headers([axum::http::header::CONTENT_TYPE]);
+        .allow_headers([
+            axum::http::header::CONTENT_TYPE,
+            axum::http::header::AUTHORIZATION,
+        ]);
     let v1 = Router::new()
-        .route("/users/{user_id}/groups", get(groups::handler_users_groups))
+        .route("/users", post(users::handler_register))
+        .route("/users/login", post(users::handler_login))
+        .route("/users/me", get(users::handler_me))
         .route(
             "/groups",
             post(groups::handler_create_group).delete(groups::handler_delete_group),
@@ -42,12 +51,87 @@ pub fn create_router(url: &str, state_server: StateServer) -> Result<Router, any
             "/groups/{token_id}/transactions/{transaction_uuid}",
             get(transactions::handler_get_transaction),
         )
+        .route(
+            "/groups/{token_id}/balances",
+            get(balances::handler_get_balances),
+        )
+        .route(
+            "/groups/{token_id}/changes",
+            get(changes::handler_get_changes),
+        )
+        .route(
+            "/groups/{token_id}/events",
+            get(events::handler_group_events),
+        )
+        .route(
+            "/groups/{token_id}/settlements",
+            get(settlements::handler_get_settlements),
+        )
+        .route(
+            "/groups/{token_id}/group_members/{member_uuid}/claim",
+            post(group_members::handler_claim_group_member)
+                .delete(group_members::handler_release_group_member),
+        )
+        .route(
+            "/groups/{token_id}/group_members/{member_uuid}/rename",
+            post(group_members::handler_rename_group_member),
+        )
+        .route(
+            "/groups/{token_id}/member_renames",
+            get(group_members::handler_member_renames),
+        )
+        .route(
+            "/groups/{token_id}/group_members/{member_uuid}/merge",
+            post(group_members::handler_merge_group_members),
+        )
+        .route(
+            "/groups/{token_id}/group_members/{member_uuid}/archive",
+            post(group_members::handler_archive_group_member)
+                .delete(group_members::handler_restore_group_member),
+        )
+        .route(
+            "/groups/{token_id}/share_links",
+            get(share_links::handler_share_links).post(share_links::handler_create_share_link),
+        )
+        .route(
+            "/groups/{token_id}/share_links/{share_token}",
+            delete(share_links::handler_revoke_share_link),
+        )
+        .route(
+            "/groups/{token_id}/token",
+            post(token_aliases::handler_rotate_token),
+        )
+        .route(
+            "/groups/{token_id}/invites",
+            get(invites::handler_invites).post(invites::handler_create_invite),
+        )
+        .route(
+            "/groups/{token_id}/invites/{invite_token}",
+            delete(invites::handler_delete_invite),
+        )
+        .route(
+            "/invites/{invite_token}/accept",
+            post(invites::handler_accept_invite),
+        )
+        .route("/groups/{token_id}/role", get(roles::handler_get_role))
+        .route(
+            "/groups/{token_id}/group_members/{member_uuid}/role",
+            put(roles::handler_set_role),
+        )
         .route(
             "/groups/{token_id}/group_members",
             get(group_members::handler_group_members)
                 .post(group_members::handler_add_group_members)
                 .delete(group_members::handler_delete_group_members),
         );
+    // Routes of one user account, only for that user
+    let user = Router::new()
+        .route("/users/{user_id}/groups", get(groups::handler_users_groups))
+        .route(
+            "/users/{user_id}/balances",
+            get(balances::handler_users_balances),
+        )
+        .route_layer(middleware::from_fn(auth::require_same_user));
     let v2 = Router::new()
         .route(
             "/groups",
@@ -57,12 +141,18 @@ pub fn create_router(url: &str, state_server: StateServer) -> Result<Router, any
             "/groups/{token_id}/transactions",
             delete(transactions::handler_delete_transactions)
                 .post(transactions::handler_modify_transactions),
-        );
+        )
+        .route(
+            "/groups/{token_id}/group_members",
+            post(group_members::handler_add_group_members_v2),
+        )
+        .route("/groups/{token_id}/sync", post(sync::handler_sync));
 
     let status = Router::new().route("/version", get(status::handler_version));
 
     let app = Router::new()
         .merge(v1)
+        .merge(user)
         .merge(status)
         .nest("/v2", v2)
         .with_state(state_server)
@@ -18,6 +18,7 @@ diesel::table! {
         token -> Text,
         created_at -> Timestamp,
         modified_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -28,7 +29,12 @@ diesel::table! {
         user_id -> Nullable<Integer>,
         uuid -> Text,
         nickname -> Text,
+        nickname_key -> Text,
         modified_at -> Timestamp,
+        role -> Text,
+        archived -> Bool,
+        weight -> Nullable<Numeric>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -41,9 +47,12 @@ diesel::table! {
         paid_by -> Integer,
         currency_id -> Text,
         exchange_rate -> Numeric,
+        kind -> Text,
+        split_mode -> Text,
         created_at -> Timestamp,
         modified_at -> Timestamp,
         uuid -> Text,
+        updated_at -> Timestamp,
     }
 }
 
@@ -53,6 +62,62 @@ diesel::table! {
         transaction_id -> Integer,
         group_member_id -> Integer,
         amount -> Numeric,
+        split_value -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    tombstones (id) {
+        id -> Integer,
+        group_id -> Integer,
+        entity -> Text,
+        uuid -> Text,
+        modified_at -> Timestamp,
+        redirect -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    share_links (id) {
+        id -> Integer,
+        group_id -> Integer,
+        token -> Text,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    token_aliases (id) {
+        id -> Integer,
+        group_id -> Integer,
+        token -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    invites (id) {
+        id -> Integer,
+        group_id -> Integer,
+        token -> Text,
+        nickname -> Nullable<Text>,
+        max_uses -> Integer,
+        uses -> Integer,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    member_renames (id) {
+        id -> Integer,
+        group_id -> Integer,
+        member_uuid -> Text,
+        old_nickname -> Text,
+        new_nickname -> Text,
+        renamed_at -> Timestamp,
     }
 }
 
@@ -63,6 +128,10 @@ diesel::joinable!(transactions -> groups (group_id));
 diesel::joinable!(transactions -> group_members (paid_by));
 diesel::joinable!(transaction_debts -> transactions (transaction_id));
 diesel::joinable!(transaction_debts -> group_members (group_member_id));
+diesel::joinable!(share_links -> groups (group_id));
+diesel::joinable!(token_aliases -> groups (group_id));
+diesel::joinable!(invites -> groups (group_id));
+diesel::joinable!(member_renames -> groups (group_id));
 
 // Enable Diesel’s ability to perform multi-table queries
 diesel::allow_tables_to_appear_in_same_query!(
@@ -71,4 +140,9 @@ diesel::allow_tables_to_appear_in_same_query!(
     group_members,
     transactions,
     transaction_debts,
+    tombstones,
+    share_links,
+    token_aliases,
+    invites,
+    member_renames,
 );
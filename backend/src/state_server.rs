@@ -1,7 +1,11 @@
 use diesel::r2d2::{self, ConnectionManager};
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 use diesel::PgConnection;
 use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::entrypoint::events::{GroupEvent, GroupEventKind};
+use crate::entrypoint::tombstones::TombstoneEntity;
 
 pub fn establish_connection() -> anyhow::Result<Arc<DbPool>> {
     let database_url = format!(
@@ -22,7 +26,30 @@ pub fn establish_connection() -> anyhow::Result<Arc<DbPool>> {
     Ok(Arc::new(pool))
 }
 
+/// Number of notifications kept for slow subscribers
+const EVENTS_CAPACITY: usize = 1024;
+
 #[derive(Debug, Clone)]
 pub struct StateServer {
     pub pool: Arc<DbPool>,
+    /// Changes of the groups, published once committed
+    pub events: broadcast::Sender<GroupEvent>,
+}
+
+impl StateServer {
+    pub fn new(pool: Arc<DbPool>) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { pool, events }
+    }
+
+    /// Notify the subscribers of the group `token` that a row changed
+    pub fn notify(&self, token: &str, kind: GroupEventKind, entity: TombstoneEntity, uuid: &str) {
+        // Nobody listening is not an error
+        let _ = self.events.send(GroupEvent {
+            token: token.to_string(),
+            kind,
+            entity,
+            uuid: uuid.to_string(),
+        });
+    }
 }
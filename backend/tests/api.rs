@@ -1,15 +1,35 @@
 use axum_test::TestServer;
+use bigdecimal::BigDecimal;
 use chrono::{self, Datelike};
 use serde_json::json;
+use share_count::entrypoint::balances::{BalancesResponse, CurrencyBalance, UserBalancesResponse};
+use share_count::entrypoint::changes::ChangesResponse;
+use share_count::entrypoint::events::{GroupEvent, GroupEventKind};
 use share_count::entrypoint::groups::GroupNoID;
+use share_count::entrypoint::invites::{AcceptInviteResponse, Invite, InviteQuery};
+use share_count::entrypoint::roles::{Role, RoleQuery};
+use share_count::entrypoint::settlements::SettlementsResponse;
+use share_count::entrypoint::share_links::ShareLink;
+use share_count::entrypoint::splits::SplitMode;
+use share_count::entrypoint::sync::{ItemResult, SyncPayload, SyncResponse, SyncStatus};
+use share_count::entrypoint::tombstones::TombstoneEntity;
+use share_count::entrypoint::users::{RegisterQuery, TokenResponse, UserResponse};
+use share_count::entrypoint::validation::{FieldError, FieldErrorCode};
 use share_count::router::create_router;
 use share_count::state_server;
+use std::collections::BTreeMap;
 use std::env;
+use std::str::FromStr;
 //use diesel_migrations::FileBasedMigrations;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
-use share_count::entrypoint::group_members::{GroupMember, GroupMemberNoDate};
-use share_count::entrypoint::transactions::{TransactionQuery, TransactionResponse};
+use share_count::entrypoint::group_members::{
+    GroupMember, GroupMemberNoDate, MemberDeleteResult, MemberDeleteStatus, MemberRename,
+    MergeMembersResponse,
+};
+use share_count::entrypoint::transactions::{
+    TransactionDelete, TransactionKind, TransactionQuery, TransactionResponse,
+};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -63,7 +83,7 @@ pub async fn create_server() -> Arc<TestServer> {
             };
             let connection = state_server::establish_connection().expect("fail connection");
 
-            let state_server = state_server::StateServer { pool: connection };
+            let state_server = state_server::StateServer::new(connection);
 
             // Start transaction for test isolation
 
@@ -81,16 +101,6 @@ pub async fn create_server() -> Arc<TestServer> {
 #[tokio::test]
 async fn manage_group() -> Result<(), anyhow::Error> {
     let server = create_server().await;
-    let response = server.get("/users/1/groups").await;
-    assert_eq!(response.status_code(), 200);
-    let json = response.json::<Vec<GroupNoID>>();
-
-    assert_eq!(json.len(), 2);
-    for group in json {
-        assert_ne!(group.name, "");
-        assert_ne!(group.currency_id, "");
-        assert!(group.created_at.date().day() > 0);
-    }
 
     //get groups per token
     println!("get groups");
@@ -136,6 +146,8 @@ async fn manage_member() -> Result<(), anyhow::Error> {
         uuid: String::from(""),
         nickname: String::from(""),
         modified_at: chrono::Utc::now().naive_utc(),
+        archived: false,
+        weight: None,
     };
     let uuid_jojo = &group
         .iter()
@@ -152,6 +164,8 @@ async fn manage_member() -> Result<(), anyhow::Error> {
             modified_at: chrono::Utc::now().naive_utc(),
             nickname: "JAJA".to_string(),
             uuid: uuid_jojo.clone(),
+            archived: false,
+            weight: None,
         }])?)
         .await;
     assert_eq!(response.status_code(), 200);
@@ -252,7 +266,11 @@ async fn manage_transactions() -> Result<(), anyhow::Error> {
         .post(format!("/groups/{token}/transactions").as_str())
         .json(&serde_json::to_value(&new_transaction)?)
         .await;
-    assert_eq!(response.status_code(), 500);
+    assert_eq!(response.status_code(), 422);
+    assert_eq!(
+        response.json::<serde_json::Value>()["code"],
+        "invalid_transaction"
+    );
 
     println!("Create new transaction...");
 
@@ -262,7 +280,7 @@ async fn manage_transactions() -> Result<(), anyhow::Error> {
         .post(format!("/groups/{token}/transactions").as_str())
         .json(&serde_json::to_value(&new_transaction)?)
         .await;
-    assert_eq!(response.status_code(), 500);
+    assert_eq!(response.status_code(), 422);
     new_transaction.set_amount("3");
     let response = server
         .post(format!("/groups/{token}/transactions").as_str())
@@ -342,8 +360,9 @@ async fn test_v2_create_and_manage_group() -> Result<(), anyhow::Error> {
         .await;
 
     assert_eq!(response.status_code(), 200);
-    let created: Vec<GroupNoID> = response.json();
+    let created: Vec<ItemResult<GroupNoID>> = response.json();
     assert_eq!(created.len(), 1);
+    assert_eq!(created[0].status, SyncStatus::Applied);
 
     Ok(())
 }
@@ -358,8 +377,8 @@ async fn test_v2_transactions_flow() -> Result<(), anyhow::Error> {
         .json(&json!([GroupNoID::new("Tokyo", "USD")]))
         .await;
     assert_eq!(response.status_code(), 200);
-    let group: Vec<GroupNoID> = response.json();
-    let token = &group[0].token;
+    let group: Vec<ItemResult<GroupNoID>> = response.json();
+    let token = &group[0].uuid;
 
     let members = vec!["Alice", "Bob"]
         .into_iter()
@@ -415,3 +434,2225 @@ async fn test_v2_transactions_flow() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn group_balances() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Rome", "EUR", &["Alice", "Bob", "Carol"], &server).await?;
+    let token = group.token;
+    let payer = members.first().unwrap().uuid.clone();
+
+    let transaction = create_transaction(&members, "Pizza", "30", "10");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transaction)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let balances = response.json::<BalancesResponse>();
+    assert_eq!(balances.currency_id, "EUR");
+    assert_eq!(balances.balances.len(), 3);
+    for balance in balances.balances {
+        if balance.member.uuid == payer {
+            assert_eq!(balance.balance, BigDecimal::from(20));
+        } else {
+            assert_eq!(balance.balance, BigDecimal::from(-10));
+        }
+    }
+
+    let response = server.get("/groups/token_unknown/balances").await;
+    assert_eq!(response.status_code(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_settlements() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Oslo", "JPY", &["Alice", "Bob", "Carol"], &server).await?;
+    let token = group.token;
+
+    let transaction = create_transaction(&members, "Museum", "90", "30");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transaction)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/settlements").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let settlements = response.json::<SettlementsResponse>();
+    assert_eq!(settlements.currency_id, "JPY");
+    assert_eq!(settlements.settlements.len(), 2);
+    for settlement in settlements.settlements {
+        assert_eq!(settlement.to.uuid, members.first().unwrap().uuid);
+        assert_eq!(settlement.amount, BigDecimal::from(30));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfer_transactions() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Lyon", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token;
+    let alice = GroupMemberNoDate::from(&members[0]);
+    let bob = GroupMemberNoDate::from(&members[1]);
+
+    let expense = create_transaction(&members, "Groceries", "20", "10");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&expense)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("Invalid transfers...");
+    let mut transfer = create_transaction(&members, "Pay back", "20", "10");
+    transfer.set_kind(TransactionKind::Transfer);
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transfer)?)
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    let mut transfer = TransactionQuery::new(&Uuid::new_v4(), "Pay back", &bob, "10");
+    transfer.add_debtor(&bob, "10");
+    transfer.set_kind(TransactionKind::Transfer);
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transfer)?)
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    println!("Transfer...");
+    let mut transfer = TransactionQuery::new(&Uuid::new_v4(), "Pay back", &bob, "10");
+    transfer.add_debtor(&alice, "10");
+    transfer.set_kind(TransactionKind::Transfer);
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transfer)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let transaction = get_transaction(&token, &transfer.get_uuid(), &server).await?;
+    assert_eq!(transaction.kind, TransactionKind::Transfer);
+
+    println!("Edit without kind...");
+    transfer.set_time(&chrono::Utc::now().naive_utc());
+    let mut body = serde_json::to_value(&transfer)?;
+    body.as_object_mut().unwrap().remove("kind");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&body)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let transaction = get_transaction(&token, &transfer.get_uuid(), &server).await?;
+    assert_eq!(transaction.kind, TransactionKind::Transfer);
+
+    println!("Invalid edit without kind...");
+    let mut invalid = transfer.clone();
+    invalid.clear_debtors();
+    invalid.add_debtor(&alice, "5");
+    invalid.add_debtor(&bob, "5");
+    let mut body = serde_json::to_value(&invalid)?;
+    body.as_object_mut().unwrap().remove("kind");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&body)
+        .await;
+    assert_eq!(response.status_code(), 422);
+    let fields = field_errors(&response);
+    assert!(fields.contains(&("debtors".to_string(), FieldErrorCode::TransferDebtorCount)));
+    assert!(fields.contains(&("paid_by".to_string(), FieldErrorCode::TransferToPayer)));
+    let transaction = get_transaction(&token, &transfer.get_uuid(), &server).await?;
+    assert_eq!(transaction.debtors.len(), 1);
+
+    let response = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let balances = response.json::<BalancesResponse>();
+    for balance in balances.balances {
+        assert_eq!(balance.balance, BigDecimal::from(0));
+        assert_eq!(balance.spent, BigDecimal::from(10));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn split_modes() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Nice", "EUR", &["Alice", "Bob", "Carol"], &server).await?;
+    let token = group.token;
+    let members = members
+        .iter()
+        .map(GroupMemberNoDate::from)
+        .collect::<Vec<GroupMemberNoDate>>();
+
+    println!("Equal split...");
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Taxi", &members[0], "10");
+    transaction.set_split_mode(SplitMode::Equal);
+    for member in &members {
+        transaction.add_split_debtor(member, None);
+    }
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transaction)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let result = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(result.split_mode, SplitMode::Equal);
+    let mut amounts = result
+        .debtors
+        .iter()
+        .map(|debt| debt.amount.to_string())
+        .collect::<Vec<String>>();
+    amounts.sort();
+    assert_eq!(amounts, vec!["3.33", "3.33", "3.34"]);
+
+    println!("Edit without split mode...");
+    transaction.clear_debtors();
+    transaction.add_debtor(&members[0], "4");
+    transaction.add_debtor(&members[1], "3");
+    transaction.add_debtor(&members[2], "3");
+    let mut body = serde_json::to_value(&transaction)?;
+    body.as_object_mut().unwrap().remove("split_mode");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&body)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let result = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(result.split_mode, SplitMode::Exact);
+    let mut amounts = result
+        .debtors
+        .iter()
+        .map(|debt| debt.amount.to_string())
+        .collect::<Vec<String>>();
+    amounts.sort();
+    assert_eq!(amounts, vec!["3", "3", "4"]);
+    assert!(result.debtors.iter().all(|debt| debt.value.is_none()));
+
+    println!("Shares split...");
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Hotel", &members[0], "100");
+    transaction.set_split_mode(SplitMode::Shares);
+    transaction.add_split_debtor(&members[0], Some("2"));
+    transaction.add_split_debtor(&members[1], Some("1"));
+    transaction.add_split_debtor(&members[2], Some("1"));
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transaction)?)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let result = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(result.split_mode, SplitMode::Shares);
+    for debt in result.debtors {
+        let share = debt.value.clone().unwrap_or_default();
+        assert_eq!(debt.amount, share * BigDecimal::from(25));
+    }
+
+    println!("Invalid percentage split...");
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Bar", &members[0], "100");
+    transaction.set_split_mode(SplitMode::Percentage);
+    transaction.add_split_debtor(&members[0], Some("50"));
+    transaction.add_split_debtor(&members[1], Some("40"));
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&serde_json::to_value(&transaction)?)
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn incremental_changes() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Porto", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token;
+
+    let mut first = create_transaction(&members, "Boat", "20", "10");
+    let second = create_transaction(&members, "Wine", "10", "5");
+    let response = server
+        .post(format!("/v2/groups/{token}/transactions").as_str())
+        .json(&vec![first.clone(), second.clone()])
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert!(changes.group.is_some());
+    assert_eq!(changes.group_members.len(), 2);
+    assert_eq!(changes.transactions.len(), 2);
+    let cursor = changes.cursor.unwrap();
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert!(changes.group.is_none());
+    assert!(changes.group_members.is_empty());
+    assert!(changes.transactions.is_empty());
+    assert_eq!(changes.cursor, Some(cursor));
+
+    first.set_description("Ferry");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&first)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert_eq!(changes.transactions.len(), 1);
+    assert_eq!(changes.transactions[0].description, "Ferry");
+    assert!(changes.cursor.unwrap() > cursor);
+    let cursor = changes.cursor.unwrap();
+
+    println!("Late offline push...");
+    let mut offline = create_transaction(&members, "Tram", "4", "2");
+    offline.set_time(&(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(20)));
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&offline)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert_eq!(changes.transactions.len(), 1);
+    assert_eq!(changes.transactions[0].uuid, offline.get_uuid());
+    assert!(changes.cursor.unwrap() > cursor);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn changes_wait_for_writers() -> Result<(), anyhow::Error> {
+    use diesel::prelude::*;
+    let server = create_server().await;
+    let (group, members) = create_group("Faro", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token;
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .await;
+    let cursor = response.json::<ChangesResponse>().cursor.unwrap();
+
+    println!("A slow writer stamps the group first...");
+    let pool = state_server::establish_connection()?;
+    let (stamped, wait_stamp) = std::sync::mpsc::channel();
+    let writer_token = token.clone();
+    let writer = std::thread::spawn(move || -> Result<(), anyhow::Error> {
+        let mut conn = pool.get()?;
+        conn.transaction::<(), anyhow::Error, _>(|conn| {
+            diesel::sql_query("UPDATE groups SET name = 'Olhão' WHERE token = $1")
+                .bind::<diesel::sql_types::Text, _>(&writer_token)
+                .execute(conn)?;
+            stamped.send(())?;
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            Ok(())
+        })
+    });
+    wait_stamp.recv()?;
+
+    println!("...and commits after a faster one...");
+    let transaction = create_transaction(&members, "Cataplana", "40", "20");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    writer.join().unwrap()?;
+    let cursor = changes.cursor.unwrap();
+    let seen_group = changes.group.map(|group| group.name);
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    let changes = response.json::<ChangesResponse>();
+    let name = seen_group.or(changes.group.map(|group| group.name));
+    assert_eq!(name.as_deref(), Some("Olhão"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn deletions_leave_tombstones() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Bern", "CHF", &["Alice", "Bob"], &server).await?;
+    let token = group.token.clone();
+
+    let mut transaction = create_transaction(&members, "Fondue", "20", "10");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .await;
+    let cursor = response.json::<ChangesResponse>().cursor.unwrap();
+
+    println!("Delete transaction...");
+    let old_version = transaction.clone();
+    transaction.set_description("Deleted");
+    let response = server
+        .delete(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("Push deleted transaction back...");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&old_version)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(format!("/groups/{token}/transactions/{}", transaction.get_uuid()).as_str())
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .add_query_param("since", cursor)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert!(changes.transactions.is_empty());
+    assert_eq!(changes.deleted.len(), 1);
+    assert_eq!(changes.deleted[0].entity, TombstoneEntity::Transaction);
+    assert_eq!(changes.deleted[0].uuid, transaction.get_uuid());
+
+    println!("Delete group...");
+    let mut deleted_group = group.clone();
+    deleted_group.modified_at = chrono::Utc::now().naive_utc();
+    let response = server.delete("/groups").json(&deleted_group).await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server.get(format!("/groups/{token}").as_str()).await;
+    assert_eq!(response.status_code(), 410);
+
+    let response = server.post("/groups").json(&group).await;
+    assert_eq!(response.status_code(), 410);
+
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let changes = response.json::<ChangesResponse>();
+    assert_eq!(changes.deleted.len(), 1);
+    assert_eq!(changes.deleted[0].entity, TombstoneEntity::Group);
+
+    Ok(())
+}
+
+fn statuses<T>(items: &[ItemResult<T>]) -> Vec<SyncStatus> {
+    items.iter().map(|item| item.status).collect()
+}
+
+#[tokio::test]
+async fn sync_batch() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let group = GroupNoID::new("Oslo", "NOK");
+    let token = group.token.clone();
+    let alice = GroupMember::new("Alice");
+    let bob = GroupMember::new("Bob");
+    let members = vec![alice.clone(), bob.clone()];
+
+    let valid = create_transaction(&members, "Ferry", "30", "15");
+    let invalid = create_transaction(&members, "Taxi", "30", "10");
+    let unknown_payer = create_transaction(&[GroupMember::new("Carol")], "Bus", "10", "10");
+
+    println!("Sync a new group...");
+    let payload = SyncPayload {
+        group: Some(group.clone()),
+        group_members: vec![alice.clone(), bob.clone(), GroupMember::new("Alice")],
+        transactions: vec![valid.clone(), invalid, unknown_payer],
+        deleted_group_members: vec![bob.clone()],
+        ..Default::default()
+    };
+    let response = server
+        .post(format!("/v2/groups/{token}/sync").as_str())
+        .json(&payload)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let result = response.json::<SyncResponse>();
+    assert_eq!(result.group.unwrap().status, SyncStatus::Applied);
+    assert_eq!(
+        statuses(&result.group_members),
+        vec![
+            SyncStatus::Applied,
+            SyncStatus::Applied,
+            SyncStatus::Conflict
+        ]
+    );
+    assert_eq!(
+        statuses(&result.transactions),
+        vec![
+            SyncStatus::Applied,
+            SyncStatus::Invalid,
+            SyncStatus::Invalid
+        ]
+    );
+    assert!(result.transactions[1].message.is_some());
+    assert_eq!(
+        statuses(&result.deleted_group_members),
+        vec![SyncStatus::Conflict]
+    );
+
+    assert_eq!(get_group_members(&token, &server).await?.len(), 2);
+    let response = server
+        .get(format!("/groups/{token}/transactions").as_str())
+        .await;
+    assert_eq!(response.json::<Vec<TransactionResponse>>().len(), 1);
+
+    println!("Sync outdated changes...");
+    let mut old_group = group.clone();
+    old_group.name = "Bergen".to_string();
+    let mut old_transaction = valid.clone();
+    old_transaction.set_time(&(valid.get_modified_at() - chrono::Duration::seconds(10)));
+    let mut deleted = valid.clone();
+    deleted.set_time(&(valid.get_modified_at() + chrono::Duration::seconds(10)));
+    let payload = SyncPayload {
+        group: Some(old_group),
+        transactions: vec![old_transaction],
+        deleted_transactions: vec![TransactionDelete {
+            uuid: deleted.get_uuid(),
+            modified_at: deleted.get_modified_at(),
+        }],
+        deleted_group_members: vec![bob.clone()],
+        ..Default::default()
+    };
+    let response = server
+        .post(format!("/v2/groups/{token}/sync").as_str())
+        .json(&payload)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let result = response.json::<SyncResponse>();
+    assert_eq!(result.group.unwrap().status, SyncStatus::Stale);
+    assert_eq!(statuses(&result.transactions), vec![SyncStatus::Stale]);
+    assert_eq!(
+        statuses(&result.deleted_transactions),
+        vec![SyncStatus::Applied]
+    );
+    assert_eq!(
+        statuses(&result.deleted_group_members),
+        vec![SyncStatus::Stale]
+    );
+
+    let response = server.get(format!("/groups/{token}").as_str()).await;
+    assert_eq!(response.json::<GroupNoID>().name, "Oslo");
+
+    println!("Sync an unknown group...");
+    let payload = SyncPayload {
+        group_members: vec![GroupMember::new("Dave")],
+        ..Default::default()
+    };
+    let response = server
+        .post("/v2/groups/token_unknown/sync")
+        .json(&payload)
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_updates_are_reported() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Rome", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token.clone();
+
+    println!("Outdated group...");
+    let mut old_group = group.clone();
+    old_group.name = "Milan".to_string();
+    old_group.modified_at -= chrono::Duration::seconds(10);
+    let response = server.post("/v2/groups").json(&vec![old_group]).await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<ItemResult<GroupNoID>>>();
+    assert_eq!(results[0].status, SyncStatus::Stale);
+    assert_eq!(results[0].current.as_ref().unwrap().name, "Rome");
+
+    println!("Outdated and conflicting members...");
+    let mut old_alice = members[0].clone();
+    old_alice.nickname = "Alicia".to_string();
+    old_alice.modified_at -= chrono::Duration::seconds(10);
+    let response = server
+        .post(format!("/v2/groups/{token}/group_members").as_str())
+        .json(&vec![
+            old_alice,
+            GroupMember::new("Bob"),
+            GroupMember::new("Carol"),
+        ])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<ItemResult<GroupMember>>>();
+    assert_eq!(
+        statuses(&results),
+        vec![SyncStatus::Stale, SyncStatus::Conflict, SyncStatus::Applied]
+    );
+    assert_eq!(
+        results[0].current.as_ref().unwrap().nickname,
+        members[0].nickname
+    );
+    assert_eq!(results[1].current.as_ref().unwrap().uuid, members[1].uuid);
+    assert_eq!(results[2].current.as_ref().unwrap().nickname, "Carol");
+
+    println!("Outdated transaction...");
+    let transaction = create_transaction(&members, "Pizza", "20", "10");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let result = response.json::<ItemResult<TransactionResponse>>();
+    assert_eq!(result.status, SyncStatus::Applied);
+
+    let mut old_transaction = transaction.clone();
+    old_transaction.set_description("Pasta");
+    old_transaction.set_time(&(transaction.get_modified_at() - chrono::Duration::seconds(10)));
+    let response = server
+        .post(format!("/v2/groups/{token}/transactions").as_str())
+        .json(&vec![old_transaction])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<ItemResult<TransactionResponse>>>();
+    assert_eq!(results[0].status, SyncStatus::Stale);
+    assert_eq!(results[0].current.as_ref().unwrap().description, "Pizza");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_events() -> Result<(), anyhow::Error> {
+    dotenvy::from_filename(".env.test").ok();
+    let state_server = state_server::StateServer::new(state_server::establish_connection()?);
+    let mut events = state_server.events.subscribe();
+    let server = TestServer::new(create_router(&env::var("FRONT_URL")?, state_server)?)?;
+
+    let response = server.get("/groups/token_unknown/events").await;
+    assert_eq!(response.status_code(), 404);
+
+    let (group, members) = create_group("Porto", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token.clone();
+    let mut transaction = create_transaction(&members, "Wine", "20", "10");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    transaction.set_description("Deleted");
+    let response = server
+        .delete(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+        if event.token == token {
+            received.push(event);
+        }
+    }
+    let event = |kind, entity, uuid: &str| GroupEvent {
+        token: token.clone(),
+        kind,
+        entity,
+        uuid: uuid.to_string(),
+    };
+    assert_eq!(
+        received,
+        vec![
+            event(GroupEventKind::Updated, TombstoneEntity::Group, &token),
+            event(
+                GroupEventKind::Updated,
+                TombstoneEntity::GroupMember,
+                &members[0].uuid
+            ),
+            event(
+                GroupEventKind::Updated,
+                TombstoneEntity::GroupMember,
+                &members[1].uuid
+            ),
+            event(
+                GroupEventKind::Updated,
+                TombstoneEntity::Transaction,
+                &transaction.get_uuid()
+            ),
+            event(
+                GroupEventKind::Deleted,
+                TombstoneEntity::Transaction,
+                &transaction.get_uuid()
+            ),
+        ]
+    );
+
+    Ok(())
+}
+
+async fn register(name: &str, server: &TestServer) -> Result<TokenResponse, anyhow::Error> {
+    let response = server
+        .post("/users")
+        .json(&RegisterQuery {
+            name: name.to_string(),
+            email: format!("{name}.{}@example.com", Uuid::new_v4()),
+            password: "correct horse".to_string(),
+        })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    Ok(response.json::<TokenResponse>())
+}
+
+#[tokio::test]
+async fn user_accounts() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+
+    println!("Register...");
+    let account = register("Alice", &server).await?;
+    let email = account.user.email.clone();
+
+    let response = server
+        .post("/users")
+        .json(&RegisterQuery {
+            name: "Other Alice".to_string(),
+            email: email.to_uppercase(),
+            password: "battery staple".to_string(),
+        })
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "email_taken");
+
+    let response = server
+        .post("/users")
+        .json(&RegisterQuery {
+            name: "Bob".to_string(),
+            email: format!("bob.{}@example.com", Uuid::new_v4()),
+            password: "short".to_string(),
+        })
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    println!("Login...");
+    let response = server
+        .post("/users/login")
+        .json(&json!({"email": email, "password": "wrong password"}))
+        .await;
+    assert_eq!(response.status_code(), 401);
+    assert_eq!(
+        response.json::<serde_json::Value>()["code"],
+        "invalid_credentials"
+    );
+
+    let response = server
+        .post("/users/login")
+        .json(&json!({"email": email, "password": "correct horse"}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let login = response.json::<TokenResponse>();
+    assert_eq!(login.user.id, account.user.id);
+
+    println!("Current user...");
+    let response = server
+        .get("/users/me")
+        .authorization_bearer(&login.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<UserResponse>().email, email);
+
+    let response = server.get("/users/me").await;
+    assert_eq!(response.status_code(), 401);
+
+    let response = server
+        .get("/users/me")
+        .authorization_bearer("not a token")
+        .await;
+    assert_eq!(response.status_code(), 401);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn claim_members() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let alice = register("Alice", &server).await?;
+    let bob = register("Bob", &server).await?;
+    let (paris, paris_members) = create_group("Paris", "EUR", &["Alice", "Bob"], &server).await?;
+    let (lyon, lyon_members) = create_group("Lyon", "EUR", &["Alice", "Bob"], &server).await?;
+    let (tokyo, tokyo_members) = create_group("Tokyo", "JPY", &["Alice", "Bob"], &server).await?;
+    let claim = |token: &str, member: &GroupMember| {
+        format!("/groups/{token}/group_members/{}/claim", member.uuid)
+    };
+
+    println!("Claim members...");
+    for (group, members) in [
+        (&paris, &paris_members),
+        (&lyon, &lyon_members),
+        (&tokyo, &tokyo_members),
+    ] {
+        let response = server
+            .post(&claim(&group.token, &members[0]))
+            .authorization_bearer(&alice.token)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    }
+    let response = server.post(&claim(&paris.token, &paris_members[1])).await;
+    assert_eq!(response.status_code(), 401);
+    let response = server
+        .post(&claim(&paris.token, &paris_members[0]))
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 409);
+    let response = server
+        .post(&claim(&paris.token, &paris_members[1]))
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 409);
+    let response = server
+        .post(&claim(&paris.token, &GroupMember::new("Nobody")))
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    println!("Release a member...");
+    let response = server
+        .delete(&claim(&lyon.token, &lyon_members[0]))
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 404);
+    let response = server
+        .delete(&claim(&lyon.token, &lyon_members[0]))
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "last_owner");
+    let response = server
+        .post(&claim(&lyon.token, &lyon_members[1]))
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .put(
+            format!(
+                "/groups/{}/group_members/{}/role",
+                lyon.token, lyon_members[1].uuid
+            )
+            .as_str(),
+        )
+        .authorization_bearer(&alice.token)
+        .json(&RoleQuery { role: Role::Owner })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .delete(&claim(&lyon.token, &lyon_members[0]))
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("My groups...");
+    let response = server
+        .get(format!("/users/{}/groups", alice.user.id).as_str())
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let mut groups = response
+        .json::<Vec<GroupNoID>>()
+        .into_iter()
+        .map(|group| group.token)
+        .collect::<Vec<String>>();
+    groups.sort();
+    let mut expected = vec![paris.token.clone(), tokyo.token.clone()];
+    expected.sort();
+    assert_eq!(groups, expected);
+
+    println!("My balance across groups...");
+    for (group, members, user, amount, share) in [
+        (&paris, &paris_members, &alice, "30", "15"),
+        (&lyon, &lyon_members, &bob, "10", "5"),
+        (&tokyo, &tokyo_members, &alice, "1000", "500"),
+    ] {
+        let transaction = create_transaction(members, "Dinner", amount, share);
+        let response = server
+            .post(format!("/groups/{}/transactions", group.token).as_str())
+            .authorization_bearer(&user.token)
+            .json(&transaction)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    }
+    let response = server
+        .get(format!("/users/{}/balances", alice.user.id).as_str())
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let balances = response.json::<UserBalancesResponse>();
+    assert_eq!(balances.groups.len(), 2);
+    assert_eq!(
+        balances.totals,
+        vec![
+            CurrencyBalance {
+                currency_id: "EUR".to_string(),
+                balance: BigDecimal::from(15),
+            },
+            CurrencyBalance {
+                currency_id: "JPY".to_string(),
+                balance: BigDecimal::from(500),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_routes_refuse_other_callers() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let alice = register("Alice", &server).await?;
+    let bob = register("Bob", &server).await?;
+    let (group, members) = create_group("Madrid", "EUR", &["Alice"], &server).await?;
+    let response = server
+        .post(
+            format!(
+                "/groups/{}/group_members/{}/claim",
+                group.token, members[0].uuid
+            )
+            .as_str(),
+        )
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    for route in ["groups", "balances"] {
+        let url = format!("/users/{}/{route}", alice.user.id);
+        println!("{url}...");
+
+        let response = server.get(&url).await;
+        assert_eq!(response.status_code(), 401);
+        assert!(!response.text().contains(&group.token));
+
+        let response = server.get(&url).authorization_bearer(&bob.token).await;
+        assert_eq!(response.status_code(), 403);
+        assert!(!response.text().contains(&group.token));
+
+        let mut forged = alice.token.clone();
+        let last = forged.pop();
+        forged.push(if last == Some('A') { 'B' } else { 'A' });
+        let response = server.get(&url).authorization_bearer(&forged).await;
+        assert_eq!(response.status_code(), 401);
+
+        let response = server
+            .get(format!("/users/1/{route}").as_str())
+            .authorization_bearer(&alice.token)
+            .await;
+        assert_eq!(response.status_code(), 403);
+
+        let response = server.get(&url).authorization_bearer(&alice.token).await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains(&group.token));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_roles() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let alice = register("Alice", &server).await?;
+    let bob = register("Bob", &server).await?;
+    let (group, members) =
+        create_group("Athens", "EUR", &["Alice", "Bob", "Carol"], &server).await?;
+    let token = group.token.clone();
+    let role_url = format!("/groups/{token}/role");
+    let transactions_url = format!("/groups/{token}/transactions");
+    let members_url = format!("/groups/{token}/group_members");
+    let set_role =
+        |member: &GroupMember| format!("/groups/{token}/group_members/{}/role", member.uuid);
+
+    println!("A group without owner is open...");
+    let response = server.get(&role_url).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<RoleQuery>().role, Role::Owner);
+
+    println!("The first claimer becomes owner, the next ones editors...");
+    for (user, member) in [(&alice, &members[0]), (&bob, &members[1])] {
+        let response = server
+            .post(format!("/groups/{token}/group_members/{}/claim", member.uuid).as_str())
+            .authorization_bearer(&user.token)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    }
+    for (user, role) in [(&alice, Role::Owner), (&bob, Role::Editor)] {
+        let response = server
+            .get(&role_url)
+            .authorization_bearer(&user.token)
+            .await;
+        assert_eq!(response.json::<RoleQuery>().role, role);
+    }
+    let response = server.get(&role_url).await;
+    assert_eq!(response.json::<RoleQuery>().role, Role::Viewer);
+
+    println!("Editors write but do not delete...");
+    let transaction = create_transaction(&members, "Gyros", "30", "10");
+    let response = server
+        .post(&transactions_url)
+        .authorization_bearer(&bob.token)
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .delete(&transactions_url)
+        .authorization_bearer(&bob.token)
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .delete(&members_url)
+        .authorization_bearer(&bob.token)
+        .json(&vec![members[2].clone()])
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .delete("/groups")
+        .authorization_bearer(&bob.token)
+        .json(&group)
+        .await;
+    assert_eq!(response.status_code(), 403);
+
+    println!("Anonymous callers only read...");
+    let response = server
+        .post(&transactions_url)
+        .json(&create_transaction(&members, "Souvlaki", "30", "10"))
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .post(&members_url)
+        .json(&vec![GroupMember::new("Dave")])
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server.get(&transactions_url).await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("The owner manages roles...");
+    let response = server
+        .put(&set_role(&members[1]))
+        .authorization_bearer(&bob.token)
+        .json(&RoleQuery { role: Role::Owner })
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .put(&set_role(&members[2]))
+        .authorization_bearer(&alice.token)
+        .json(&RoleQuery { role: Role::Owner })
+        .await;
+    assert_eq!(response.status_code(), 409);
+    let response = server
+        .put(&set_role(&members[0]))
+        .authorization_bearer(&alice.token)
+        .json(&RoleQuery { role: Role::Editor })
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "last_owner");
+    let response = server
+        .delete(format!("/groups/{token}/group_members/{}/claim", members[0].uuid).as_str())
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "last_owner");
+    let response = server
+        .get(&role_url)
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.json::<RoleQuery>().role, Role::Owner);
+    let response = server
+        .put(&set_role(&members[1]))
+        .authorization_bearer(&alice.token)
+        .json(&RoleQuery { role: Role::Viewer })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .post(&transactions_url)
+        .authorization_bearer(&bob.token)
+        .json(&create_transaction(&members, "Souvlaki", "30", "10"))
+        .await;
+    assert_eq!(response.status_code(), 403);
+
+    println!("A viewer stays one after releasing their member...");
+    let claim_url = format!("/groups/{token}/group_members/{}/claim", members[1].uuid);
+    let response = server
+        .delete(&claim_url)
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .post(&claim_url)
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(&role_url)
+        .authorization_bearer(&bob.token)
+        .await;
+    assert_eq!(response.json::<RoleQuery>().role, Role::Viewer);
+
+    println!("The owner deletes...");
+    let mut transaction = transaction;
+    transaction.set_time(&chrono::Utc::now().naive_utc());
+    let response = server
+        .delete(&transactions_url)
+        .authorization_bearer(&alice.token)
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server.get(&transactions_url).await;
+    assert!(response.json::<Vec<TransactionResponse>>().is_empty());
+
+    println!("The owner cannot delete their own member...");
+    let alice_member = GroupMember {
+        modified_at: chrono::Utc::now().naive_utc(),
+        ..members[0].clone()
+    };
+    let response = server
+        .delete(&members_url)
+        .authorization_bearer(&alice.token)
+        .json(&vec![alice_member.clone()])
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(response.json::<serde_json::Value>()["code"], "last_owner");
+    let response = server
+        .post(format!("/v2/groups/{token}/sync").as_str())
+        .authorization_bearer(&alice.token)
+        .json(&SyncPayload {
+            deleted_group_members: vec![alice_member],
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(
+        statuses(&response.json::<SyncResponse>().deleted_group_members),
+        vec![SyncStatus::Conflict]
+    );
+    assert_eq!(get_group_members(&token, &server).await?.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_only_share_links() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Oslo", "NOK", &["Alice", "Bob"], &server).await?;
+    let token = group.token.clone();
+    let transaction = create_transaction(&members, "Waffles", "100", "50");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("Create a share link...");
+    let response = server
+        .post(format!("/groups/{token}/share_links").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let share = response.json::<ShareLink>().token;
+    assert_ne!(share, token);
+
+    println!("Read through the share link...");
+    let response = server.get(format!("/groups/{share}").as_str()).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<GroupNoID>().token, share);
+    assert!(!response.text().contains(&token));
+    for route in ["transactions", "balances", "settlements", "group_members"] {
+        let response = server
+            .get(format!("/groups/{share}/{route}").as_str())
+            .await;
+        assert_eq!(response.status_code(), 200, "{route}");
+    }
+    let response = server
+        .get(format!("/groups/{share}/transactions/{}", transaction.get_uuid()).as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(format!("/groups/{share}/changes").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert!(!response.text().contains(&token));
+    let changes = response.json::<ChangesResponse>();
+    assert_eq!(changes.group.unwrap().token, share);
+    assert_eq!(changes.transactions.len(), 1);
+
+    println!("Writes are refused...");
+    let response = server
+        .post(format!("/groups/{share}/transactions").as_str())
+        .json(&create_transaction(&members, "Cod", "100", "50"))
+        .await;
+    assert_eq!(response.status_code(), 403);
+    assert_eq!(
+        response.json::<serde_json::Value>()["code"],
+        "read_only_token"
+    );
+    let response = server
+        .delete(format!("/groups/{share}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .post(format!("/groups/{share}/group_members").as_str())
+        .json(&vec![GroupMember::new("Carol")])
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .post(format!("/v2/groups/{share}/sync").as_str())
+        .json(&SyncPayload::default())
+        .await;
+    assert_eq!(response.status_code(), 403);
+
+    println!("A share link does not create a group...");
+    let mut phantom = GroupNoID::new("Phantom", "NOK");
+    phantom.token = share.clone();
+    let response = server.post("/groups").json(&phantom).await;
+    assert_eq!(response.status_code(), 403);
+    let response = server.post("/v2/groups").json(&vec![phantom.clone()]).await;
+    assert_eq!(response.status_code(), 403);
+    let response = server
+        .post(format!("/v2/groups/{share}/sync").as_str())
+        .json(&SyncPayload {
+            group: Some(phantom),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(response.status_code(), 403);
+    let response = server.get(format!("/groups/{share}").as_str()).await;
+    assert_eq!(response.json::<GroupNoID>().name, "Oslo");
+    let response = server
+        .post(format!("/groups/{share}/share_links").as_str())
+        .await;
+    assert_eq!(response.status_code(), 403);
+
+    println!("Revoke the share link...");
+    let response = server
+        .delete(format!("/groups/{token}/share_links/{share}").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(format!("/groups/{share}/transactions").as_str())
+        .await;
+    assert_eq!(response.status_code(), 410);
+    let response = server
+        .delete(format!("/groups/{token}/share_links/{share}").as_str())
+        .await;
+    assert_eq!(response.status_code(), 404);
+    let response = server
+        .get(format!("/groups/{token}/share_links").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let links = response.json::<Vec<ShareLink>>();
+    assert_eq!(links.len(), 1);
+    assert!(links[0].revoked_at.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotate_group_token() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Lisbon", "EUR", &["Alice", "Bob"], &server).await?;
+    let old = group.token.clone();
+
+    println!("Rotate the token...");
+    let response = server
+        .post(format!("/groups/{old}/token").as_str())
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let new = response.json::<GroupNoID>().token;
+    assert_ne!(new, old);
+
+    println!("The previous token still works during the grace window...");
+    let response = server.get(format!("/groups/{old}").as_str()).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<GroupNoID>().token, new);
+    let response = server
+        .post(format!("/groups/{old}/transactions").as_str())
+        .json(&create_transaction(&members, "Pastel", "4", "2"))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .get(format!("/groups/{new}/transactions").as_str())
+        .await;
+    assert_eq!(response.json::<Vec<TransactionResponse>>().len(), 1);
+    let mut renamed = group.clone();
+    renamed.name = "Lisboa".to_string();
+    renamed.modified_at = chrono::Utc::now().naive_utc();
+    let response = server.post("/groups").json(&renamed).await;
+    assert_eq!(response.status_code(), 200);
+    let updated = response.json::<GroupNoID>();
+    assert_eq!(updated.token, new);
+    assert_eq!(updated.name, "Lisboa");
+
+    println!("Only the current token rotates...");
+    let response = server
+        .post(format!("/groups/{old}/token").as_str())
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 403);
+
+    println!("Revoke every previous token...");
+    let response = server
+        .post(format!("/groups/{new}/token").as_str())
+        .json(&json!({"revoke": true}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let newest = response.json::<GroupNoID>().token;
+    for token in [&old, &new] {
+        let response = server.get(format!("/groups/{token}").as_str()).await;
+        assert_eq!(response.status_code(), 410);
+    }
+    let response = server.get(format!("/groups/{newest}").as_str()).await;
+    assert_eq!(response.status_code(), 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_invites() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let alice = register("Alice", &server).await?;
+    let bob = register("Bob", &server).await?;
+    let carol = register("Carol", &server).await?;
+    let (group, members) = create_group("Porto", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token.clone();
+    let invites_url = format!("/groups/{token}/invites");
+    let response = server
+        .post(format!("/groups/{token}/group_members/{}/claim", members[0].uuid).as_str())
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let tomorrow = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    let invite = |nickname: Option<&str>, max_uses: i32, expires_at: chrono::NaiveDateTime| {
+        server
+            .post(&invites_url)
+            .authorization_bearer(&alice.token)
+            .json(&InviteQuery {
+                nickname: nickname.map(str::to_string),
+                max_uses,
+                expires_at,
+            })
+    };
+    let accept = |invite: &Invite| format!("/invites/{}/accept", invite.token);
+
+    println!("Only the owner manages invites...");
+    let response = server.get(&invites_url).await;
+    assert_eq!(response.status_code(), 403);
+    let response = invite(None, 0, tomorrow).await;
+    assert_eq!(response.status_code(), 422);
+
+    println!("A named member is claimed once...");
+    let response = invite(Some("Bob"), 2, tomorrow).await;
+    assert_eq!(response.status_code(), 422);
+
+    println!("Invite Bob as a named member...");
+    let response = invite(Some("Bob"), 1, tomorrow).await;
+    assert_eq!(response.status_code(), 200);
+    let for_bob = response.json::<Invite>();
+    let response = server.post(&accept(&for_bob)).json(&json!({})).await;
+    assert_eq!(response.status_code(), 401);
+    let response = server
+        .post(&accept(&for_bob))
+        .authorization_bearer(&bob.token)
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let joined = response.json::<AcceptInviteResponse>();
+    assert_eq!(joined.group.token, token);
+    assert_eq!(joined.group_member.uuid, members[1].uuid);
+    let response = server
+        .post(&accept(&for_bob))
+        .authorization_bearer(&carol.token)
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 410);
+
+    println!("Open invite...");
+    let response = invite(None, 2, tomorrow).await;
+    let open = response.json::<Invite>();
+    let response = server
+        .post(&accept(&open))
+        .authorization_bearer(&carol.token)
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 422);
+    let response = server
+        .post(&accept(&open))
+        .authorization_bearer(&carol.token)
+        .json(&json!({"nickname": "Carol"}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(
+        response
+            .json::<AcceptInviteResponse>()
+            .group_member
+            .nickname,
+        "Carol"
+    );
+    assert_eq!(get_group_members(&token, &server).await?.len(), 3);
+
+    println!("Expired and unknown invites...");
+    let yesterday = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+    let expired = invite(None, 1, yesterday).await.json::<Invite>();
+    let response = server
+        .post(&accept(&expired))
+        .authorization_bearer(&carol.token)
+        .json(&json!({"nickname": "Dave"}))
+        .await;
+    assert_eq!(response.status_code(), 410);
+    let response = server
+        .post("/invites/unknown/accept")
+        .authorization_bearer(&carol.token)
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    println!("List and delete invites...");
+    let response = server
+        .get(&invites_url)
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let invites = response.json::<Vec<Invite>>();
+    assert_eq!(invites.len(), 3);
+    assert_eq!(invites[1].uses, 1);
+    let response = server
+        .delete(format!("{invites_url}/{}", open.token).as_str())
+        .authorization_bearer(&alice.token)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .post(&accept(&open))
+        .authorization_bearer(&carol.token)
+        .json(&json!({}))
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    Ok(())
+}
+
+fn field_errors(response: &axum_test::TestResponse) -> Vec<(String, FieldErrorCode)> {
+    let body = response.json::<serde_json::Value>();
+    assert_eq!(body["code"], "invalid_transaction");
+    serde_json::from_value::<Vec<FieldError>>(body["content"].clone())
+        .unwrap()
+        .into_iter()
+        .map(|error| (error.field, error.code))
+        .collect()
+}
+
+#[tokio::test]
+async fn transaction_validation_errors() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Vienna", "EUR", &["Alice", "Bob"], &server).await?;
+    let url = format!("/groups/{}/transactions", group.token);
+    let members = members
+        .iter()
+        .cloned()
+        .map(GroupMemberNoDate::from)
+        .collect::<Vec<_>>();
+
+    println!("Every problem is reported...");
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Schnitzel", &members[0], "10");
+    transaction.add_debtor(&members[0], "5");
+    transaction.add_debtor(&members[0], "3");
+    transaction.add_debtor(&members[1], "-1");
+    let mut body = serde_json::to_value(&transaction)?;
+    body["currency_id"] = json!("XXX");
+    body["exchange_rate"] = json!("0");
+    let response = server.post(&url).json(&body).await;
+    assert_eq!(response.status_code(), 422);
+    assert_eq!(
+        field_errors(&response),
+        vec![
+            ("exchange_rate".to_string(), FieldErrorCode::NotPositive),
+            ("currency_id".to_string(), FieldErrorCode::UnknownCurrency),
+            (
+                "debtors[1].member".to_string(),
+                FieldErrorCode::DuplicateMember
+            ),
+            ("debtors[2].amount".to_string(), FieldErrorCode::Negative),
+            ("debtors".to_string(), FieldErrorCode::SumMismatch),
+        ]
+    );
+    let content = response.json::<serde_json::Value>()["content"].clone();
+    assert_eq!(content[4]["params"]["expected"], "10");
+    assert_eq!(content[4]["params"]["actual"], "7");
+
+    println!("Payer and debtors belong to the group...");
+    let ghost = GroupMemberNoDate::from(GroupMember::new("Ghost"));
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Sachertorte", &ghost, "10");
+    transaction.add_debtor(&members[0], "5");
+    transaction.add_debtor(&ghost, "5");
+    let response = server.post(&url).json(&transaction).await;
+    assert_eq!(response.status_code(), 422);
+    assert_eq!(
+        field_errors(&response),
+        vec![
+            ("paid_by".to_string(), FieldErrorCode::UnknownMember),
+            (
+                "debtors[1].member".to_string(),
+                FieldErrorCode::UnknownMember
+            ),
+        ]
+    );
+
+    let response = server
+        .post(format!("/v2/groups/{}/sync", group.token).as_str())
+        .json(&SyncPayload {
+            transactions: vec![transaction],
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let result = response.json::<SyncResponse>();
+    assert_eq!(result.transactions[0].status, SyncStatus::Invalid);
+    assert_eq!(result.transactions[0].errors.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_debtors() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Lisbon", "EUR", &["Alice"], &server).await?;
+    let url = format!("/groups/{}/transactions", group.token);
+    let alice = GroupMemberNoDate::from(members[0].clone());
+    let carol = GroupMemberNoDate::from(GroupMember::new("Carol"));
+
+    println!("An unknown debtor is rejected...");
+    let mut transaction = TransactionQuery::new(&Uuid::new_v4(), "Pasteis", &alice, "10");
+    transaction.add_debtor(&alice, "5");
+    transaction.add_debtor(&carol, "5");
+    let response = server.post(&url).json(&transaction).await;
+    assert_eq!(response.status_code(), 422);
+    assert_eq!(
+        field_errors(&response),
+        vec![(
+            "debtors[1].member".to_string(),
+            FieldErrorCode::UnknownMember
+        )]
+    );
+    let content = response.json::<serde_json::Value>()["content"].clone();
+    assert_eq!(content[0]["params"]["uuid"], json!(carol.uuid));
+    let response = server
+        .get(format!("{url}/{}", transaction.get_uuid()).as_str())
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    println!("...or created on demand");
+    let response = server
+        .post(format!("{url}?create_members=true").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let group_members = get_group_members(&group.token, &server).await?;
+    assert_eq!(group_members.len(), 2);
+    assert!(group_members
+        .iter()
+        .any(|member| member.uuid == carol.uuid && member.nickname == "Carol"));
+    let created = get_transaction(&group.token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(created.debtors.len(), 2);
+
+    println!("A group made offline syncs without listing its members...");
+    let offline = GroupNoID::new("Porto", "EUR");
+    let transaction = create_transaction(
+        &[GroupMember::new("Dan"), GroupMember::new("Eve")],
+        "Francesinha",
+        "20",
+        "10",
+    );
+    let response = server
+        .post(format!("/v2/groups/{}/sync", offline.token).as_str())
+        .json(&SyncPayload {
+            group: Some(offline.clone()),
+            transactions: vec![transaction],
+            create_members: true,
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let result = response.json::<SyncResponse>();
+    assert_eq!(statuses(&result.transactions), vec![SyncStatus::Applied]);
+    assert_eq!(get_group_members(&offline.token, &server).await?.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn edit_debtors() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) =
+        create_group("Athens", "EUR", &["Alice", "Bob", "Carol"], &server).await?;
+    let token = group.token;
+    let url = format!("/groups/{token}/transactions");
+    let [alice, bob, carol] = [0, 1, 2].map(|i| GroupMemberNoDate::from(members[i].clone()));
+
+    let mut transaction = create_transaction(&members, "Souvlaki", "30", "10");
+    let response = server.post(&url).json(&transaction).await;
+    assert_eq!(response.status_code(), 200);
+
+    let debts = |transaction: &TransactionResponse| {
+        let mut debts = transaction
+            .debtors
+            .iter()
+            .map(|debt| (debt.member.nickname.clone(), debt.amount.clone()))
+            .collect::<Vec<_>>();
+        debts.sort();
+        debts
+    };
+    let balance_of = |balances: &BalancesResponse, uuid: &str| {
+        balances
+            .balances
+            .iter()
+            .find(|balance| balance.member.uuid == uuid)
+            .map(|balance| balance.balance.clone())
+            .unwrap_or_default()
+    };
+
+    println!("Shrink the split...");
+    transaction.clear_debtors();
+    transaction.add_debtor(&alice, "15");
+    transaction.add_debtor(&bob, "15");
+    let response = server.post(&url).json(&transaction).await;
+    assert_eq!(response.status_code(), 200);
+    let current = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(
+        debts(&current),
+        vec![
+            ("Alice".to_string(), BigDecimal::from(15)),
+            ("Bob".to_string(), BigDecimal::from(15)),
+        ]
+    );
+    let balances = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await
+        .json::<BalancesResponse>();
+    assert_eq!(balance_of(&balances, &alice.uuid), BigDecimal::from(15));
+    assert_eq!(balance_of(&balances, &bob.uuid), BigDecimal::from(-15));
+    assert_eq!(balance_of(&balances, &carol.uuid), BigDecimal::from(0));
+
+    println!("Reshuffle the split...");
+    transaction.clear_debtors();
+    transaction.add_debtor(&carol, "20");
+    transaction.add_debtor(&bob, "10");
+    let response = server.post(&url).json(&transaction).await;
+    assert_eq!(response.status_code(), 200);
+    let current = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(
+        debts(&current),
+        vec![
+            ("Bob".to_string(), BigDecimal::from(10)),
+            ("Carol".to_string(), BigDecimal::from(20)),
+        ]
+    );
+    let balances = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await
+        .json::<BalancesResponse>();
+    assert_eq!(balance_of(&balances, &alice.uuid), BigDecimal::from(30));
+    assert_eq!(balance_of(&balances, &bob.uuid), BigDecimal::from(-10));
+    assert_eq!(balance_of(&balances, &carol.uuid), BigDecimal::from(-20));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn member_deletion_results() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group(
+        "Madrid",
+        "EUR",
+        &["Alice", "Bob", "Carol", "Dan", "Eve"],
+        &server,
+    )
+    .await?;
+    let token = group.token;
+    let url = format!("/groups/{token}/group_members");
+    let [alice, bob, carol, dan, eve] = [0, 1, 2, 3, 4].map(|i| members[i].clone());
+
+    let tapas = create_transaction(&members[..3], "Tapas", "30", "10");
+    let mut churros = TransactionQuery::new(
+        &Uuid::new_v4(),
+        "Churros",
+        &GroupMemberNoDate::from(&carol),
+        "10",
+    );
+    churros.add_debtor(&GroupMemberNoDate::from(&carol), "5");
+    churros.add_debtor(&GroupMemberNoDate::from(&alice), "5");
+    for transaction in [&tapas, &churros] {
+        let response = server
+            .post(format!("/groups/{token}/transactions").as_str())
+            .json(transaction)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    }
+    let now = |member: &GroupMember| GroupMember {
+        modified_at: chrono::Utc::now().naive_utc(),
+        ..member.clone()
+    };
+
+    println!("Each member gets a result...");
+    let response = server
+        .delete(&url)
+        .json(&vec![
+            now(&bob),
+            now(&dan),
+            now(&GroupMember::new("Ghost")),
+            eve.clone(),
+        ])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<MemberDeleteResult>>();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| result.status)
+            .collect::<Vec<_>>(),
+        vec![
+            MemberDeleteStatus::BlockedByDebts,
+            MemberDeleteStatus::Deleted,
+            MemberDeleteStatus::NotFound,
+            MemberDeleteStatus::Stale,
+        ]
+    );
+    assert_eq!(results[0].transactions, vec![tapas.get_uuid()]);
+    assert_eq!(get_group_members(&token, &server).await?.len(), 4);
+
+    println!("The reassign target cannot be deleted with them...");
+    let response = server
+        .delete(format!("{url}?force=true&reassign_to={}", bob.uuid).as_str())
+        .json(&vec![now(&bob)])
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    println!("Force the deletion, reassigning the shares...");
+    let response = server
+        .delete(format!("{url}?force=true&reassign_to={}", carol.uuid).as_str())
+        .json(&vec![now(&bob)])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<MemberDeleteResult>>();
+    assert_eq!(results[0].status, MemberDeleteStatus::Deleted);
+    assert_eq!(results[0].transactions, vec![tapas.get_uuid()]);
+    let current = get_transaction(&token, &tapas.get_uuid(), &server).await?;
+    let mut debts = current
+        .debtors
+        .iter()
+        .map(|debt| (debt.member.nickname.clone(), debt.amount.clone()))
+        .collect::<Vec<_>>();
+    debts.sort();
+    assert_eq!(
+        debts,
+        vec![
+            ("Alice".to_string(), BigDecimal::from(10)),
+            ("Carol".to_string(), BigDecimal::from(20)),
+        ]
+    );
+
+    println!("A payer is not taken out...");
+    let response = server
+        .delete(format!("{url}?force=true").as_str())
+        .json(&vec![now(&alice)])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<MemberDeleteResult>>();
+    assert_eq!(results[0].status, MemberDeleteStatus::BlockedByPayments);
+    assert_eq!(results[0].transactions, vec![tapas.get_uuid()]);
+    assert_eq!(get_group_members(&token, &server).await?.len(), 3);
+
+    println!("Force the deletion, taking the shares out...");
+    let mut sangria = TransactionQuery::new(
+        &Uuid::new_v4(),
+        "Sangria",
+        &GroupMemberNoDate::from(&alice),
+        "15",
+    );
+    for member in [&alice, &carol, &eve] {
+        sangria.add_debtor(&GroupMemberNoDate::from(member), "5");
+    }
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&sangria)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let balance = |balances: &BalancesResponse, member: &GroupMember| {
+        balances
+            .balances
+            .iter()
+            .find(|balance| balance.member.uuid == member.uuid)
+            .map(|balance| balance.balance.clone())
+    };
+    let before = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await
+        .json::<BalancesResponse>();
+
+    let response = server
+        .delete(format!("{url}?force=true").as_str())
+        .json(&vec![now(&eve)])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<MemberDeleteResult>>();
+    assert_eq!(results[0].status, MemberDeleteStatus::Deleted);
+    assert_eq!(results[0].transactions, vec![sangria.get_uuid()]);
+    let current = get_transaction(&token, &sangria.get_uuid(), &server).await?;
+    assert_eq!(current.amount, BigDecimal::from(10));
+    assert_eq!(current.debtors.len(), 2);
+    assert_eq!(get_group_members(&token, &server).await?.len(), 2);
+
+    let after = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await
+        .json::<BalancesResponse>();
+    // Carol's balance is untouched, the payer bears the removed share
+    assert_eq!(balance(&after, &carol), balance(&before, &carol));
+    assert_eq!(
+        balance(&after, &alice),
+        balance(&before, &alice).map(|balance| balance - BigDecimal::from(5))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn archive_members() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) = create_group("Dublin", "EUR", &["Alice", "Bob"], &server).await?;
+    let token = group.token;
+    let bob = &members[1];
+
+    let transaction = create_transaction(&members, "Rent", "800", "400");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&transaction)
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("Archive a former flatmate...");
+    let response = server
+        .post(format!("/groups/{token}/group_members/{}/archive", bob.uuid).as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert!(response.json::<GroupMember>().archived);
+
+    let group_members = get_group_members(&token, &server).await?;
+    assert_eq!(group_members.len(), 2);
+    assert!(group_members
+        .iter()
+        .any(|member| member.uuid == bob.uuid && member.archived));
+    let response = server
+        .get(format!("/groups/{token}/group_members?active=true").as_str())
+        .await;
+    let active = response.json::<Vec<GroupMember>>();
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].nickname, "Alice");
+
+    println!("The history stays...");
+    let current = get_transaction(&token, &transaction.get_uuid(), &server).await?;
+    assert_eq!(current.debtors.len(), 2);
+    let balances = server
+        .get(format!("/groups/{token}/balances").as_str())
+        .await
+        .json::<BalancesResponse>();
+    let bob_balance = balances
+        .balances
+        .iter()
+        .find(|balance| balance.member.uuid == bob.uuid)
+        .map(|balance| balance.balance.clone());
+    assert_eq!(bob_balance, Some(BigDecimal::from(-400)));
+
+    println!("Saving the member does not restore it...");
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&vec![GroupMember {
+            nickname: "Robert".to_string(),
+            modified_at: chrono::Utc::now().naive_utc(),
+            ..bob.clone()
+        }])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let group_members = get_group_members(&token, &server).await?;
+    assert!(group_members
+        .iter()
+        .any(|member| member.nickname == "Robert" && member.archived));
+
+    println!("Restore the member...");
+    let response = server
+        .delete(format!("/groups/{token}/group_members/{}/archive", bob.uuid).as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert!(!response.json::<GroupMember>().archived);
+    let response = server
+        .post(format!("/groups/{token}/group_members/unknown/archive").as_str())
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn merge_members() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) =
+        create_group("Berlin", "EUR", &["Alex", "Alexander", "Sam"], &server).await?;
+    let token = group.token;
+    let [alex, duplicate, sam] = [0, 1, 2].map(|i| GroupMemberNoDate::from(members[i].clone()));
+
+    // Both Alexes owe a share of the same transaction
+    let mut currywurst = TransactionQuery::new(&Uuid::new_v4(), "Currywurst", &sam, "30");
+    currywurst.add_debtor(&alex, "10");
+    currywurst.add_debtor(&duplicate, "10");
+    currywurst.add_debtor(&sam, "10");
+    let mut doner = TransactionQuery::new(&Uuid::new_v4(), "Döner", &duplicate, "12");
+    doner.add_debtor(&duplicate, "6");
+    doner.add_debtor(&sam, "6");
+    for transaction in [&currywurst, &doner] {
+        let response = server
+            .post(format!("/groups/{token}/transactions").as_str())
+            .json(transaction)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    }
+
+    println!("Merge the duplicate...");
+    let url = format!("/groups/{token}/group_members/{}/merge", alex.uuid);
+    let response = server
+        .post(&url)
+        .json(&json!({"duplicate": alex.uuid}))
+        .await;
+    assert_eq!(response.status_code(), 422);
+    let response = server
+        .post(&url)
+        .json(&json!({"duplicate": duplicate.uuid}))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let merge = response.json::<MergeMembersResponse>();
+    assert_eq!(merge.group_member.uuid, alex.uuid);
+    assert_eq!(merge.transactions.len(), 2);
+
+    let current = get_transaction(&token, &currywurst.get_uuid(), &server).await?;
+    let mut debts = current
+        .debtors
+        .iter()
+        .map(|debt| (debt.member.nickname.clone(), debt.amount.clone()))
+        .collect::<Vec<_>>();
+    debts.sort();
+    assert_eq!(
+        debts,
+        vec![
+            ("Alex".to_string(), BigDecimal::from(20)),
+            ("Sam".to_string(), BigDecimal::from(10)),
+        ]
+    );
+    let current = get_transaction(&token, &doner.get_uuid(), &server).await?;
+    assert_eq!(current.paid_by.uuid, alex.uuid);
+    assert_eq!(get_group_members(&token, &server).await?.len(), 2);
+
+    println!("The duplicate uuid redirects...");
+    let response = server
+        .get(format!("/groups/{token}/changes").as_str())
+        .await;
+    let changes = response.json::<ChangesResponse>();
+    let tombstone = changes
+        .deleted
+        .iter()
+        .find(|tombstone| tombstone.uuid == duplicate.uuid)
+        .unwrap();
+    assert_eq!(tombstone.redirect.as_deref(), Some(alex.uuid.as_str()));
+
+    let mut late = TransactionQuery::new(&Uuid::new_v4(), "Club Mate", &duplicate, "4");
+    late.add_debtor(&duplicate, "2");
+    late.add_debtor(&alex, "2");
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&late)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let current = get_transaction(&token, &late.get_uuid(), &server).await?;
+    assert_eq!(current.paid_by.uuid, alex.uuid);
+    assert_eq!(current.debtors.len(), 1);
+    assert_eq!(current.debtors[0].amount, BigDecimal::from(4));
+
+    // The duplicate does not come back from an offline device
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&vec![GroupMember {
+            modified_at: chrono::Utc::now().naive_utc(),
+            ..members[1].clone()
+        }])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(get_group_members(&token, &server).await?.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rename_members() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let (group, members) =
+        create_group("Seville", "EUR", &["Alex", "Sam", "José"], &server).await?;
+    let token = group.token;
+    let [alex, sam, jose] = [0, 1, 2].map(|i| members[i].clone());
+    let rename = |uuid: &str| format!("/groups/{token}/group_members/{uuid}/rename");
+
+    println!("Nicknames collide whatever the case and Unicode form...");
+    for nickname in ["ALEX", " alex "] {
+        let response = server
+            .post(&rename(&sam.uuid))
+            .json(&json!({ "nickname": nickname }))
+            .await;
+        assert_eq!(response.status_code(), 409);
+        let body = response.json::<serde_json::Value>();
+        assert_eq!(body["code"], "nickname_taken");
+        assert_eq!(body["content"]["uuid"], json!(alex.uuid));
+    }
+    let response = server
+        .post(&rename(&sam.uuid))
+        .json(&json!({ "nickname": "Jose\u{301}" }))
+        .await;
+    assert_eq!(response.status_code(), 409);
+    assert_eq!(
+        response.json::<serde_json::Value>()["content"]["uuid"],
+        json!(jose.uuid)
+    );
+    let response = server
+        .post(&rename(&sam.uuid))
+        .json(&json!({ "nickname": "  " }))
+        .await;
+    assert_eq!(response.status_code(), 422);
+    let response = server
+        .post(&rename("unknown"))
+        .json(&json!({ "nickname": "Kim" }))
+        .await;
+    assert_eq!(response.status_code(), 404);
+
+    println!("Added members collide too...");
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&vec![
+            GroupMember::new("Kim"),
+            GroupMember::new("jose\u{301}"),
+        ])
+        .await;
+    assert_eq!(response.status_code(), 409);
+    let body = response.json::<serde_json::Value>();
+    assert_eq!(body["code"], "nickname_taken");
+    assert_eq!(body["content"]["uuid"], json!(jose.uuid));
+    assert_eq!(get_group_members(&token, &server).await?.len(), 3);
+    let response = server
+        .post(format!("/v2/groups/{token}/group_members").as_str())
+        .json(&vec![GroupMember::new("SAM")])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<ItemResult<GroupMember>>>();
+    assert_eq!(statuses(&results), vec![SyncStatus::Conflict]);
+    assert_eq!(results[0].current.as_ref().unwrap().uuid, sam.uuid);
+
+    println!("An outdated push is stale even with a taken nickname...");
+    let response = server
+        .post(format!("/v2/groups/{token}/group_members").as_str())
+        .json(&vec![GroupMember {
+            nickname: "Alex".to_string(),
+            modified_at: sam.modified_at - chrono::Duration::seconds(10),
+            ..sam.clone()
+        }])
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let results = response.json::<Vec<ItemResult<GroupMember>>>();
+    assert_eq!(statuses(&results), vec![SyncStatus::Stale]);
+    assert_eq!(results[0].current.as_ref().unwrap().nickname, "Sam");
+
+    println!("Rename members...");
+    let response = server
+        .post(&rename(&alex.uuid))
+        .json(&json!({ "nickname": "alex" }))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<GroupMember>().nickname, "alex");
+    let response = server
+        .post(&rename(&sam.uuid))
+        .json(&json!({ "nickname": "Samuel" }))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&vec![GroupMember {
+            nickname: "Sammy".to_string(),
+            modified_at: chrono::Utc::now().naive_utc(),
+            ..sam.clone()
+        }])
+        .await;
+    assert_eq!(response.status_code(), 200);
+
+    println!("The history is kept...");
+    let response = server
+        .get(format!("/groups/{token}/member_renames").as_str())
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let renames = response
+        .json::<Vec<MemberRename>>()
+        .into_iter()
+        .map(|rename| (rename.member_uuid, rename.old_nickname, rename.new_nickname))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        renames,
+        vec![
+            (alex.uuid.clone(), "Alex".to_string(), "alex".to_string()),
+            (sam.uuid.clone(), "Sam".to_string(), "Samuel".to_string()),
+            (sam.uuid.clone(), "Samuel".to_string(), "Sammy".to_string()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_member_weights() -> Result<(), anyhow::Error> {
+    let server = create_server().await;
+    let group = GroupNoID::new("Bruges", "EUR");
+    let token = group.token.clone();
+    let couple = GroupMember::new("Couple").with_weight(BigDecimal::from(2));
+    let child = GroupMember::new("Child").with_weight(BigDecimal::from_str("0.5")?);
+    let solo = GroupMember::new("Solo");
+
+    let response = server
+        .post(format!("/v2/groups/{token}/sync").as_str())
+        .json(&SyncPayload {
+            group: Some(group.clone()),
+            group_members: vec![couple.clone(), child.clone(), solo.clone()],
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let group_members = get_group_members(&token, &server).await?;
+    assert!(group_members
+        .iter()
+        .any(|member| member.uuid == couple.uuid && member.weight == Some(BigDecimal::from(2))));
+
+    println!("A save without weight keeps it...");
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&json!([{
+            "uuid": couple.uuid,
+            "nickname": "Couple",
+            "modified_at": chrono::Utc::now().naive_utc(),
+        }]))
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let group_members = get_group_members(&token, &server).await?;
+    assert!(group_members
+        .iter()
+        .any(|member| member.uuid == couple.uuid && member.weight == Some(BigDecimal::from(2))));
+
+    let debts = |transaction: &TransactionResponse| {
+        transaction
+            .debtors
+            .iter()
+            .map(|debt| (debt.member.nickname.clone(), debt.amount.to_string()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let [couple, child, solo] = [&couple, &child, &solo].map(GroupMemberNoDate::from);
+
+    println!("An equal split follows the default weights...");
+    let mut waffles = TransactionQuery::new(&Uuid::new_v4(), "Waffles", &solo, "35");
+    waffles.set_split_mode(SplitMode::Equal);
+    for member in [&couple, &child, &solo] {
+        waffles.add_split_debtor(member, None);
+    }
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&waffles)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let current = get_transaction(&token, &waffles.get_uuid(), &server).await?;
+    assert_eq!(
+        debts(&current),
+        BTreeMap::from([
+            ("Child".to_string(), "5.00".to_string()),
+            ("Couple".to_string(), "20.00".to_string()),
+            ("Solo".to_string(), "10.00".to_string()),
+        ])
+    );
+
+    println!("...and so do the missing shares of a shares split");
+    let mut fries = TransactionQuery::new(&Uuid::new_v4(), "Fries", &solo, "30");
+    fries.set_split_mode(SplitMode::Shares);
+    fries.add_split_debtor(&couple, None);
+    fries.add_split_debtor(&child, Some("1"));
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&fries)
+        .await;
+    assert_eq!(response.status_code(), 200);
+    let current = get_transaction(&token, &fries.get_uuid(), &server).await?;
+    assert_eq!(
+        debts(&current),
+        BTreeMap::from([
+            ("Child".to_string(), "10.00".to_string()),
+            ("Couple".to_string(), "20.00".to_string()),
+        ])
+    );
+
+    // A member without default weight still needs a share
+    let mut beer = TransactionQuery::new(&Uuid::new_v4(), "Beer", &solo, "10");
+    beer.set_split_mode(SplitMode::Shares);
+    beer.add_split_debtor(&solo, None);
+    let response = server
+        .post(format!("/groups/{token}/transactions").as_str())
+        .json(&beer)
+        .await;
+    assert_eq!(response.status_code(), 422);
+    assert_eq!(
+        field_errors(&response),
+        vec![("debtors[0].value".to_string(), FieldErrorCode::MissingValue)]
+    );
+
+    println!("Weights cannot be negative...");
+    let response = server
+        .post(format!("/groups/{token}/group_members").as_str())
+        .json(&vec![
+            GroupMember::new("Ghost").with_weight(BigDecimal::from(-1))
+        ])
+        .await;
+    assert_eq!(response.status_code(), 422);
+
+    Ok(())
+}
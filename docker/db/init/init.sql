@@ -1,3 +1,8 @@
+drop TABLE IF EXISTS member_renames;
+drop TABLE IF EXISTS invites;
+drop TABLE IF EXISTS token_aliases;
+drop TABLE IF EXISTS share_links;
+drop TABLE IF EXISTS tombstones;
 drop TABLE IF EXISTS transaction_debts;
 drop TABLE IF EXISTS transactions;
 drop TABLE IF EXISTS group_members;
@@ -20,7 +25,8 @@ CREATE TABLE groups (
   currency_id TEXT NOT NULL,
   token TEXT NOT NULL UNIQUE,
   modified_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
 );
 
 -- GROUP MEMBERS
@@ -28,10 +34,18 @@ CREATE TABLE group_members (
   id SERIAL PRIMARY KEY,
   group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
   nickname TEXT NOT NULL,
+  -- Nickname trimmed, NFKC-normalised and lowercased, unique in the group
+  nickname_key TEXT NOT NULL,
   user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
   uuid TEXT NOT NULL UNIQUE,
   modified_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-  UNIQUE (group_id, nickname)
+  role TEXT NOT NULL DEFAULT 'editor' CHECK (role IN ('owner', 'editor', 'viewer')),
+  -- Archived members keep their history but are hidden when adding transactions
+  archived BOOLEAN NOT NULL DEFAULT FALSE,
+  -- Default weight of the member in equal and shares splits
+  weight NUMERIC CHECK (weight >= 0),
+  updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+  UNIQUE (group_id, nickname_key)
 );
 
 -- TRANSACTIONS
@@ -43,9 +57,12 @@ CREATE TABLE transactions (
   paid_by INTEGER NOT NULL REFERENCES group_members(id),
   currency_id TEXT NOT NULL,
   exchange_rate NUMERIC NOT NULL DEFAULT 1,
+  kind TEXT NOT NULL DEFAULT 'expense' CHECK (kind IN ('expense', 'transfer', 'income')),
+  split_mode TEXT NOT NULL DEFAULT 'exact' CHECK (split_mode IN ('exact', 'equal', 'shares', 'percentage')),
   created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
   modified_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-  uuid TEXT NOT NULL UNIQUE
+  uuid TEXT NOT NULL UNIQUE,
+  updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
 );
 
 -- TRANSACTION DEBTS
@@ -54,9 +71,96 @@ CREATE TABLE transaction_debts (
   transaction_id INTEGER NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
   group_member_id INTEGER NOT NULL REFERENCES group_members(id) ON DELETE CASCADE,
   amount NUMERIC NOT NULL,
+  split_value NUMERIC,
   UNIQUE (transaction_id, group_member_id)
 );
 
+-- TOMBSTONES
+-- Rows deleted on the server, so that offline clients do not push them back.
+-- group_id has no foreign key: the tombstone of a group outlives it.
+CREATE TABLE tombstones (
+  id SERIAL PRIMARY KEY,
+  group_id INTEGER NOT NULL,
+  entity TEXT NOT NULL CHECK (entity IN ('group', 'group_member', 'transaction')),
+  uuid TEXT NOT NULL,
+  modified_at TIMESTAMP NOT NULL,
+  -- Uuid of the row the deleted one was merged into
+  redirect TEXT,
+  updated_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC'),
+  UNIQUE (entity, uuid)
+);
+
+-- UPDATED AT
+-- Server time of the last write of a row, used as the /changes cursor:
+-- modified_at comes from the clients and may be far in the past for an
+-- offline push. A writer holds a shared advisory lock on the group until it
+-- commits, so that /changes can wait for the rows stamped before its cursor.
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_TABLE_NAME = 'groups' THEN
+    PERFORM pg_advisory_xact_lock_shared(NEW.id);
+  ELSE
+    PERFORM pg_advisory_xact_lock_shared(NEW.group_id);
+  END IF;
+  NEW.updated_at = clock_timestamp() AT TIME ZONE 'UTC';
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER groups_updated_at BEFORE INSERT OR UPDATE ON groups
+  FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+CREATE TRIGGER group_members_updated_at BEFORE INSERT OR UPDATE ON group_members
+  FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+CREATE TRIGGER transactions_updated_at BEFORE INSERT OR UPDATE ON transactions
+  FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+CREATE TRIGGER tombstones_updated_at BEFORE INSERT OR UPDATE ON tombstones
+  FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- SHARE LINKS
+-- Read-only tokens of a group, revoked ones are kept to answer GONE.
+CREATE TABLE share_links (
+  id SERIAL PRIMARY KEY,
+  group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  token TEXT NOT NULL UNIQUE,
+  created_at TIMESTAMP NOT NULL,
+  revoked_at TIMESTAMP
+);
+
+-- TOKEN ALIASES
+-- Previous tokens of a rotated group, working until expires_at.
+CREATE TABLE token_aliases (
+  id SERIAL PRIMARY KEY,
+  group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  token TEXT NOT NULL UNIQUE,
+  expires_at TIMESTAMP NOT NULL
+);
+
+-- INVITES
+-- Links for a logged-in user to join a group, as `nickname` if given.
+CREATE TABLE invites (
+  id SERIAL PRIMARY KEY,
+  group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  token TEXT NOT NULL UNIQUE,
+  nickname TEXT,
+  max_uses INTEGER NOT NULL CHECK (max_uses > 0),
+  uses INTEGER NOT NULL DEFAULT 0,
+  expires_at TIMESTAMP NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  -- Only one user can claim the named member
+  CHECK (nickname IS NULL OR max_uses = 1)
+);
+
+-- MEMBER RENAMES
+-- Previous nicknames of the members, kept after the member is deleted.
+CREATE TABLE member_renames (
+  id SERIAL PRIMARY KEY,
+  group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  member_uuid TEXT NOT NULL,
+  old_nickname TEXT NOT NULL,
+  new_nickname TEXT NOT NULL,
+  renamed_at TIMESTAMP NOT NULL
+);
+
 -- SEED DATA
 INSERT INTO users (name, email, password_hash, created_at)
 VALUES 
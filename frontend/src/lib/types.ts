@@ -9,6 +9,8 @@ export interface GroupMember {
     uuid: string,
     nickname: string
     modified_at: string,
+    // Default weight in equal and shares splits, 1 if absent
+    weight?: string | null,
 }
 
 export interface Transaction {